@@ -77,7 +77,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let _ = futures::join!(supervisor_task, send_halt);
     // run this to see that the interval is not properly stopped if the ctx is stopped
-    // futures::join!(supervisor_task, send_panic); // there is no panic recovery
+    // futures::join!(supervisor_task, send_panic); // a panic in a handler restarts the actor
 
     Ok(())
 }
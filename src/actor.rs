@@ -1,10 +1,17 @@
-use crate::addr::ActorEvent;
+use crate::addr::{ActorEvent, ExecFn, ExitReason};
 use crate::error::Result;
+use crate::mailbox::{Mailbox, MailboxReceiver};
+use crate::metrics::HandlerTimer;
 use crate::runtime::spawn;
-use crate::{Addr, Context};
-use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use crate::system;
+use crate::trace::{self, SenderSpan};
+use crate::{ActorId, Addr, Context, SupervisorStrategy};
 use futures::channel::oneshot;
 use futures::{Future, FutureExt, StreamExt};
+use std::any::Any;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Represents a message that can be handled by the actor.
 pub trait Message: 'static + Send {
@@ -13,6 +20,13 @@ pub trait Message: 'static + Send {
     type Result: 'static + Send;
 }
 
+/// A notification shared between its recipients, so that it does not need to be cloned for each of them.
+///
+/// See `Addr::publish_shared`.
+impl<T: Message<Result = ()> + Sync> Message for Arc<T> {
+    type Result = ();
+}
+
 /// Describes how to handle messages of a specific type.
 /// Implementing Handler is a general way to handle incoming messages.
 /// The type T is a message which can be handled by the actor.
@@ -94,7 +108,6 @@ pub trait Actor: Sized + Send + 'static {
     /// #[message(result = "i32")]
     /// struct MyMsg(i32);
     ///
-    /// #[async_trait::async_trait]
     /// impl Handler<MyMsg> for MyActor {
     ///     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: MyMsg) -> i32 {
     ///         msg.0 * msg.0
@@ -117,18 +130,198 @@ pub trait Actor: Sized + Send + 'static {
     }
 }
 
+/// Configures how an actor is started.
+///
+/// # Examples
+///
+/// ```rust
+/// use xactor::*;
+///
+/// #[message]
+/// struct Work;
+///
+/// struct MyActor;
+///
+/// impl Actor for MyActor {}
+///
+/// impl Handler<Work> for MyActor {
+///     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Work) {}
+/// }
+///
+/// #[xactor::main]
+/// async fn main() -> Result<()> {
+///     // At most 16 messages can be queued in the mailbox
+///     let addr = ActorBuilder::new().mailbox_capacity(16).start(MyActor).await?;
+///
+///     // Wait for a free slot instead of failing with `ActorError::MailboxFull`
+///     addr.send_async(Work).await?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ActorBuilder {
+    pub(crate) mailbox_capacity: Option<usize>,
+    pub(crate) call_timeout: Option<Duration>,
+    pub(crate) supervisor_strategy: SupervisorStrategy,
+    pub(crate) name: Option<Arc<str>>,
+}
+
+impl ActorBuilder {
+    /// Create a builder with the default settings (unbounded mailbox).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the number of messages that can be queued in the actor's mailbox.
+    ///
+    /// When the mailbox is full, `Addr::send`, `Addr::try_send` and `Sender::send` fail with `ActorError::MailboxFull`,
+    /// while `Addr::send_async`, `Addr::call` and `Caller::call` wait for a free slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0, since no message could ever be queued.
+    pub fn mailbox_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "the mailbox capacity must be at least 1");
+        self.mailbox_capacity = Some(capacity);
+        self
+    }
+
+    /// Fail calls made with `Addr::call` and `Caller::call` with `ActorError::Timeout` if the actor does not reply within `timeout`.
+    pub fn call_timeout(mut self, timeout: Duration) -> Self {
+        self.call_timeout = Some(timeout);
+        self
+    }
+
+    /// Give the actor a name, reported by `System::actors`.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into().into());
+        self
+    }
+
+    /// Set the restart policy used by `start_supervised`.
+    pub fn supervisor_strategy(mut self, strategy: SupervisorStrategy) -> Self {
+        self.supervisor_strategy = strategy;
+        self
+    }
+
+    /// Start a new actor with this configuration, returning its address.
+    pub fn start<A: Actor>(self, actor: A) -> impl Future<Output = Result<Addr<A>>> + Send {
+        ActorManager::with_builder(&self).start_actor(actor)
+    }
+
+    /// Start a new supervised actor with this configuration, returning its address.
+    ///
+    /// See `Supervisor::start`.
+    pub fn start_supervised<A, F>(self, f: F) -> impl Future<Output = Result<Addr<A>>> + Send
+    where
+        A: Actor,
+        F: Fn() -> A + Send + 'static,
+    {
+        crate::Supervisor::start_with(self, f)
+    }
+}
+
+/// Run the handler of `msg` in a child span of `span`, recording how long it took.
+pub(crate) async fn handle_message<A, T>(
+    actor: &mut A,
+    ctx: &mut Context<A>,
+    msg: T,
+    span: SenderSpan,
+) -> T::Result
+where
+    A: Handler<T>,
+    T: Message,
+{
+    let actor_id = ctx.actor_id();
+    run_handler::<A, T, _>(actor_id, span, Handler::handle(actor, ctx, msg)).await
+}
+
+/// Run `handler`, the handler of a message of type `T` for the actor `A`, in a child span of `span`,
+/// recording how long it took.
+pub(crate) async fn run_handler<A, T, F>(
+    actor_id: ActorId,
+    span: SenderSpan,
+    handler: F,
+) -> F::Output
+where
+    F: Future,
+{
+    let timer = HandlerTimer::start();
+    let res = span.in_handler::<A, T, _>(actor_id, handler).await;
+    timer.finish::<A, T>(actor_id);
+    res
+}
+
+/// Extract the message of a panic payload.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+/// Run an event, catching any panic raised by the handler.
+async fn exec_event<A>(
+    f: ExecFn<A>,
+    actor: &mut A,
+    ctx: &mut Context<A>,
+) -> std::thread::Result<()> {
+    AssertUnwindSafe(f(actor, ctx)).catch_unwind().await
+}
+
+/// Process the events of an actor until it stops, returning the reason it stopped.
+pub(crate) async fn run_event_loop<A>(
+    actor: &mut A,
+    ctx: &mut Context<A>,
+    rx: &mut MailboxReceiver<A>,
+) -> ExitReason {
+    while let Some(event) = rx.next().await {
+        match event {
+            ActorEvent::Exec(f) | ActorEvent::Notify(f) => {
+                if exec_event(f, actor, ctx).await.is_err() {
+                    return ExitReason::Panicked;
+                }
+            }
+            ActorEvent::Stop(err) => return ExitReason::from_stop(err),
+            ActorEvent::RemoveStream(id) => {
+                let mut streams = ctx.streams.lock().unwrap();
+
+                if streams.contains(id) {
+                    streams.remove(id);
+                }
+            }
+        }
+    }
+    ExitReason::AllAddressesDropped
+}
+
+/// Stop the children of an actor, call its `stopped` method and cancel its streams and intervals.
+pub(crate) async fn stop_actor<A: Actor>(actor: &mut A, ctx: &mut Context<A>) {
+    ctx.stop_children().await;
+    actor.stopped(ctx).await;
+    ctx.abort_streams();
+    ctx.abort_intervals();
+}
+
 pub(crate) struct ActorManager<A: Actor> {
     ctx: Context<A>,
-    tx: std::sync::Arc<UnboundedSender<ActorEvent<A>>>,
-    rx: UnboundedReceiver<ActorEvent<A>>,
-    tx_exit: oneshot::Sender<()>,
+    tx: Arc<Mailbox<A>>,
+    rx: MailboxReceiver<A>,
+    tx_exit: oneshot::Sender<ExitReason>,
 }
 
 impl<A: Actor> ActorManager<A> {
     pub(crate) fn new() -> Self {
+        Self::with_builder(&ActorBuilder::default())
+    }
+
+    pub(crate) fn with_builder(builder: &ActorBuilder) -> Self {
         let (tx_exit, rx_exit) = oneshot::channel();
         let rx_exit = rx_exit.shared();
-        let (ctx, rx, tx) = Context::new(Some(rx_exit));
+        let (ctx, rx, tx) = Context::new(Some(rx_exit), builder);
         Self {
             ctx,
             rx,
@@ -155,35 +348,25 @@ impl<A: Actor> ActorManager<A> {
         // Call started
         actor.started(&mut ctx).await?;
 
+        let addr = Addr {
+            actor_id,
+            tx,
+            rx_exit,
+        };
+        let seq = system::register(&addr);
+        trace::started::<A>(actor_id);
+
         spawn({
             async move {
-                while let Some(event) = rx.next().await {
-                    match event {
-                        ActorEvent::Exec(f) => f(&mut actor, &mut ctx).await,
-                        ActorEvent::Stop(_err) => break,
-                        ActorEvent::RemoveStream(id) => {
-                            let mut streams = ctx.streams.lock().unwrap();
-
-                            if streams.contains(id) {
-                                streams.remove(id);
-                            }
-                        }
-                    }
-                }
-
-                actor.stopped(&mut ctx).await;
-
-                ctx.abort_streams();
-                ctx.abort_intervals();
-
-                tx_exit.send(()).ok();
+                let exit_reason = run_event_loop(&mut actor, &mut ctx, &mut rx).await;
+                rx.set_stopping();
+                stop_actor(&mut actor, &mut ctx).await;
+                trace::stopped::<A>(actor_id, &exit_reason);
+                system::unregister(seq, &exit_reason);
+                tx_exit.send(exit_reason).ok();
             }
         });
 
-        Ok(Addr {
-            actor_id,
-            tx,
-            rx_exit,
-        })
+        Ok(addr)
     }
 }
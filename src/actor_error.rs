@@ -0,0 +1,110 @@
+use crate::{ActorId, ExitReason};
+use std::fmt;
+
+/// The errors returned by xactor itself.
+///
+/// They are returned wrapped in `xactor::Error`, and can be recovered with `downcast_ref`.
+///
+/// # Examples
+///
+/// ```rust
+/// use xactor::*;
+///
+/// #[message]
+/// struct Ping;
+///
+/// struct MyActor;
+///
+/// impl Actor for MyActor {}
+///
+/// impl Handler<Ping> for MyActor {
+///     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Ping) {}
+/// }
+///
+/// #[xactor::main]
+/// async fn main() -> Result<()> {
+///     let mut addr = MyActor.start().await?;
+///     addr.stop(None)?;
+///     addr.clone().wait_for_stop().await;
+///
+///     let err = addr.call(Ping).await.unwrap_err();
+///     assert!(matches!(err.downcast_ref::<ActorError>(), Some(ActorError::Disconnected)));
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ActorError {
+    /// The actor has stopped, or its address was dropped.
+    Disconnected,
+    /// The bounded mailbox of the actor is full.
+    MailboxFull,
+    /// The actor did not reply to a call in time.
+    Timeout,
+    /// No service of the requested type is registered.
+    ServiceNotFound,
+    /// An instance of the service is already running.
+    ServiceAlreadyRunning,
+    /// A service depends on itself, through the services with the given type names.
+    ///
+    /// See `Dependency`.
+    ServiceDependencyCycle(Vec<&'static str>),
+    /// Another actor is already registered under this name in the `Registry`.
+    NameTaken(String),
+    /// No actor is registered under this name in the `Registry`, with the requested type.
+    NameNotFound(String),
+    /// The handler of the message panicked, with the given panic message.
+    ///
+    /// The panic also stops the actor (or restarts it, if it is supervised), with `ExitReason::Panicked`.
+    HandlerPanicked(String),
+    /// An actor linked to this one has failed.
+    ///
+    /// See `Context::link`.
+    LinkFailed {
+        /// The id of the linked actor that failed.
+        actor_id: ActorId,
+        /// The reason the linked actor stopped.
+        reason: ExitReason,
+    },
+    /// A child of the same `SupervisorGroup` has failed, and the group has stopped all its children.
+    ChildFailed {
+        /// The id of the child that failed.
+        actor_id: ActorId,
+        /// The reason the child stopped.
+        reason: ExitReason,
+    },
+}
+
+impl fmt::Display for ActorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActorError::Disconnected => f.write_str("actor disconnected"),
+            ActorError::MailboxFull => f.write_str("mailbox full"),
+            ActorError::Timeout => f.write_str("call timed out"),
+            ActorError::ServiceNotFound => f.write_str("service not found"),
+            ActorError::ServiceAlreadyRunning => f.write_str("service already running"),
+            ActorError::ServiceDependencyCycle(cycle) => {
+                write!(f, "service dependency cycle: {}", cycle.join(" -> "))
+            }
+            ActorError::NameTaken(name) => write!(f, "name already registered: {}", name),
+            ActorError::NameNotFound(name) => write!(f, "name not found: {}", name),
+            ActorError::HandlerPanicked(message) => write!(f, "handler panicked: {}", message),
+            ActorError::LinkFailed {
+                actor_id,
+                reason: ExitReason::Stopped(err),
+            } => write!(f, "linked actor {} failed: {}", actor_id, err),
+            ActorError::LinkFailed { actor_id, .. } => {
+                write!(f, "linked actor {} panicked", actor_id)
+            }
+            ActorError::ChildFailed {
+                actor_id,
+                reason: ExitReason::Stopped(err),
+            } => write!(f, "child actor {} failed: {}", actor_id, err),
+            ActorError::ChildFailed { actor_id, .. } => {
+                write!(f, "child actor {} panicked", actor_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ActorError {}
@@ -1,18 +1,74 @@
-use crate::{Actor, ActorId, Caller, Context, Error, Handler, Message, Result, Sender};
-use futures::channel::{mpsc, oneshot};
+use crate::actor::{handle_message, panic_message};
+use crate::mailbox::Mailbox;
+use crate::runtime;
+use crate::trace::SenderSpan;
+use crate::{Actor, ActorError, ActorId, Caller, Context, Error, Handler, Message, Result, Sender};
+use futures::channel::oneshot;
 use futures::future::Shared;
-use futures::Future;
+use futures::{Future, FutureExt};
 use std::hash::{Hash, Hasher};
+use std::panic::{self, AssertUnwindSafe};
 use std::pin::Pin;
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 
 type ExecFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
 
 pub(crate) type ExecFn<A> =
     Box<dyn for<'a> FnOnce(&'a mut A, &'a mut Context<A>) -> ExecFuture<'a> + Send + 'static>;
 
+/// Resolves to the reason the actor stopped, once it has exited.
+pub(crate) type ExitReceiver = Shared<oneshot::Receiver<ExitReason>>;
+
+/// Returns `false` once the actor that `rx_exit` belongs to has stopped, or failed to start.
+///
+/// An actor without an exit receiver is always alive.
+pub(crate) fn is_alive(rx_exit: Option<&ExitReceiver>) -> bool {
+    match rx_exit {
+        Some(rx_exit) => rx_exit.clone().now_or_never().is_none(),
+        None => true,
+    }
+}
+
+/// The reason an actor stopped.
+#[derive(Debug, Clone)]
+pub enum ExitReason {
+    /// The actor was stopped without an error.
+    Normal,
+    /// The actor was stopped with an error.
+    Stopped(Arc<Error>),
+    /// All addresses of the actor were dropped.
+    AllAddressesDropped,
+    /// A message handler of the actor panicked.
+    Panicked,
+}
+
+impl ExitReason {
+    pub(crate) fn from_stop(err: Option<Error>) -> Self {
+        match err {
+            Some(err) => ExitReason::Stopped(Arc::new(err)),
+            None => ExitReason::Normal,
+        }
+    }
+
+    /// Returns the error the actor was stopped with, if any.
+    pub fn error(&self) -> Option<&Arc<Error>> {
+        match self {
+            ExitReason::Stopped(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the actor was stopped with an error or panicked.
+    pub fn is_failure(&self) -> bool {
+        matches!(self, ExitReason::Stopped(_) | ExitReason::Panicked)
+    }
+}
+
 pub(crate) enum ActorEvent<A> {
     Exec(ExecFn<A>),
+    /// A message from xactor itself, such as `Terminated`, which does not count against the capacity limit.
+    Notify(ExecFn<A>),
     Stop(Option<Error>),
     RemoveStream(usize),
 }
@@ -23,8 +79,8 @@ pub(crate) enum ActorEvent<A> {
 /// You can use `Clone` trait to create multiple copies of `Addr<A>`.
 pub struct Addr<A> {
     pub(crate) actor_id: ActorId,
-    pub(crate) tx: Arc<mpsc::UnboundedSender<ActorEvent<A>>>,
-    pub(crate) rx_exit: Option<Shared<oneshot::Receiver<()>>>,
+    pub(crate) tx: Arc<Mailbox<A>>,
+    pub(crate) rx_exit: Option<ExitReceiver>,
 }
 
 impl<A> Clone for Addr<A> {
@@ -45,6 +101,11 @@ impl<A> Addr<A> {
             rx_exit: self.rx_exit.clone(),
         }
     }
+
+    /// Returns `false` once the actor has stopped, or failed to start.
+    pub(crate) fn is_alive(&self) -> bool {
+        is_alive(self.rx_exit.as_ref())
+    }
 }
 
 impl<A> PartialEq for Addr<A> {
@@ -69,41 +130,116 @@ impl<A: Actor> Addr<A> {
 
     /// Stop the actor.
     pub fn stop(&mut self, err: Option<Error>) -> Result<()> {
-        mpsc::UnboundedSender::clone(&*self.tx).start_send(ActorEvent::Stop(err))?;
-        Ok(())
+        self.tx.send_event(ActorEvent::Stop(err))
     }
 
     /// Send a message `msg` to the actor and wait for the return value.
+    ///
+    /// If the actor was started with a default call timeout, this fails with `ActorError::Timeout` once it has elapsed.
+    ///
+    /// If the handler panics, this fails with `ActorError::HandlerPanicked` and the actor is stopped.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use xactor::*;
+    ///
+    /// #[message(result = "i32")]
+    /// struct Divide(i32, i32);
+    ///
+    /// struct MyActor;
+    ///
+    /// impl Actor for MyActor {}
+    ///
+    /// impl Handler<Divide> for MyActor {
+    ///     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: Divide) -> i32 {
+    ///         msg.0 / msg.1
+    ///     }
+    /// }
+    ///
+    /// #[xactor::main]
+    /// async fn main() -> Result<()> {
+    ///     let addr = MyActor.start().await?;
+    ///     assert_eq!(addr.call(Divide(6, 3)).await?, 2);
+    ///
+    ///     let err = addr.call(Divide(1, 0)).await.unwrap_err();
+    ///     assert!(matches!(err.downcast_ref::<ActorError>(), Some(ActorError::HandlerPanicked(_))));
+    ///
+    ///     assert!(matches!(addr.wait_for_stop().await, ExitReason::Panicked));
+    ///     Ok(())
+    /// }
+    /// ```
     pub async fn call<T: Message>(&self, msg: T) -> Result<T::Result>
     where
         A: Handler<T>,
     {
-        let (tx, rx) = oneshot::channel();
-        mpsc::UnboundedSender::clone(&*self.tx).start_send(ActorEvent::Exec(Box::new(
-            move |actor, ctx| {
-                Box::pin(async move {
-                    let res = Handler::handle(actor, ctx, msg).await;
-                    let _ = tx.send(res);
-                })
-            },
-        )))?;
+        call_mailbox(&self.tx, msg, self.tx.call_timeout).await
+    }
 
-        Ok(rx.await?)
+    /// Send a message `msg` to the actor and wait for the return value,
+    /// failing with `ActorError::Timeout` if it does not arrive within `timeout`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use xactor::*;
+    /// use std::time::Duration;
+    ///
+    /// #[message]
+    /// struct Hang;
+    ///
+    /// struct MyActor;
+    ///
+    /// impl Actor for MyActor {}
+    ///
+    /// impl Handler<Hang> for MyActor {
+    ///     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Hang) {
+    ///         sleep(Duration::from_secs(1)).await;
+    ///     }
+    /// }
+    ///
+    /// #[xactor::main]
+    /// async fn main() -> Result<()> {
+    ///     let addr = MyActor.start().await?;
+    ///
+    ///     let err = addr.call_timeout(Hang, Duration::from_millis(100)).await.unwrap_err();
+    ///     assert!(matches!(err.downcast_ref::<ActorError>(), Some(ActorError::Timeout)));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn call_timeout<T: Message>(&self, msg: T, timeout: Duration) -> Result<T::Result>
+    where
+        A: Handler<T>,
+    {
+        call_mailbox(&self.tx, msg, Some(timeout)).await
     }
 
     /// Send a message `msg` to the actor without waiting for the return value.
+    ///
+    /// If the actor was started with a bounded mailbox, this behaves like `try_send`.
     pub fn send<T: Message<Result = ()>>(&self, msg: T) -> Result<()>
     where
         A: Handler<T>,
     {
-        mpsc::UnboundedSender::clone(&*self.tx).start_send(ActorEvent::Exec(Box::new(
-            move |actor, ctx| {
-                Box::pin(async move {
-                    Handler::handle(actor, ctx, msg).await;
-                })
-            },
-        )))?;
-        Ok(())
+        self.try_send(msg)
+    }
+
+    /// Send a message `msg` to the actor without waiting for the return value,
+    /// failing with `ActorError::MailboxFull` if the actor's bounded mailbox has no free slot.
+    pub fn try_send<T: Message<Result = ()>>(&self, msg: T) -> Result<()>
+    where
+        A: Handler<T>,
+    {
+        self.tx.try_send(send_event(msg))
+    }
+
+    /// Send a message `msg` to the actor without waiting for the return value,
+    /// waiting for a free slot if the actor's bounded mailbox is full.
+    pub async fn send_async<T: Message<Result = ()>>(&self, msg: T) -> Result<()>
+    where
+        A: Handler<T>,
+    {
+        self.tx.send(send_event(msg)).await
     }
 
     /// Create a `Caller<T>` for a specific message type
@@ -117,24 +253,10 @@ impl<A: Actor> Addr<A> {
             let weak_tx_option = weak_tx.upgrade();
             Box::pin(async move {
                 match weak_tx_option {
-                    Some(tx) => {
-                        let (oneshot_tx, oneshot_rx) = oneshot::channel();
-
-                        mpsc::UnboundedSender::clone(&tx).start_send(ActorEvent::Exec(
-                            Box::new(move |actor, ctx| {
-                                Box::pin(async move {
-                                    let res = Handler::handle(&mut *actor, ctx, msg).await;
-                                    let _ = oneshot_tx.send(res);
-                                })
-                            }),
-                        ))?;
-
-                        let result = oneshot_rx.await?;
-                        Ok(result)
-                    }
-                    None => Err(crate::error::anyhow!("Actor Dropped")),
+                    Some(tx) => call_mailbox(&tx, msg, tx.call_timeout).await,
+                    None => Err(ActorError::Disconnected.into()),
                 }
-            }) as Pin<Box<dyn Future<Output = Result<T::Result>>>>
+            }) as Pin<Box<dyn Future<Output = Result<T::Result>> + Send>>
         };
 
         Caller {
@@ -151,16 +273,7 @@ impl<A: Actor> Addr<A> {
         let weak_tx = Arc::downgrade(&self.tx);
 
         let closure = move |msg| match weak_tx.upgrade() {
-            Some(tx) => {
-                mpsc::UnboundedSender::clone(&tx).start_send(ActorEvent::Exec(Box::new(
-                    move |actor, ctx| {
-                        Box::pin(async move {
-                            Handler::handle(&mut *actor, ctx, msg).await;
-                        })
-                    },
-                )))?;
-                Ok(())
-            }
+            Some(tx) => tx.try_send(send_event(msg)),
             None => Ok(()),
         };
 
@@ -172,19 +285,108 @@ impl<A: Actor> Addr<A> {
     }
 
     /// Wait for an actor to finish, and if the actor has finished, the function returns immediately.
-    pub async fn wait_for_stop(self) {
+    ///
+    /// Returns the reason the actor stopped.
+    /// For a supervised actor this is the last failure seen before the supervisor gave up restarting it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use xactor::*;
+    ///
+    /// struct MyActor;
+    ///
+    /// impl Actor for MyActor {}
+    ///
+    /// #[xactor::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut addr = MyActor.start().await?;
+    ///     addr.stop(Some(error::anyhow!("shutting down")))?;
+    ///
+    ///     match addr.wait_for_stop().await {
+    ///         ExitReason::Stopped(err) => assert_eq!(err.to_string(), "shutting down"),
+    ///         reason => panic!("unexpected exit reason: {:?}", reason),
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn wait_for_stop(self) -> ExitReason {
         if let Some(rx_exit) = self.rx_exit {
-            rx_exit.await.ok();
+            // The exit channel is only dropped without a value if the actor's task panicked
+            rx_exit.await.unwrap_or(ExitReason::Panicked)
         } else {
-            futures::future::pending::<()>().await;
+            futures::future::pending().await
+        }
+    }
+}
+
+/// Send `msg` through `tx` and wait for the reply, for at most `timeout` if set.
+async fn call_mailbox<A, T>(tx: &Mailbox<A>, msg: T, timeout: Option<Duration>) -> Result<T::Result>
+where
+    A: Handler<T>,
+    T: Message,
+{
+    let call = async move {
+        let (oneshot_tx, oneshot_rx) = oneshot::channel();
+        tx.send(call_event(msg, oneshot_tx)).await?;
+        Ok(oneshot_rx.await.map_err(|_| ActorError::Disconnected)??)
+    };
+    match timeout {
+        Some(timeout) => runtime::timeout(timeout, call).await?,
+        None => call.await,
+    }
+}
+
+/// Build the event that handles `msg`, without waiting for the result.
+pub(crate) fn send_event<A, T>(msg: T) -> ExecFn<A>
+where
+    A: Handler<T>,
+    T: Message<Result = ()>,
+{
+    let span = SenderSpan::current();
+    Box::new(move |actor, ctx| {
+        Box::pin(async move {
+            handle_message(actor, ctx, msg, span).await;
+        })
+    })
+}
+
+/// Build the event that handles `msg` and sends the result back through `tx`, see `reply`.
+fn call_event<A, T>(
+    msg: T,
+    tx: oneshot::Sender<std::result::Result<T::Result, ActorError>>,
+) -> ExecFn<A>
+where
+    A: Handler<T>,
+    T: Message,
+{
+    let span = SenderSpan::current();
+    Box::new(move |actor, ctx| Box::pin(reply(handle_message(actor, ctx, msg, span), tx)))
+}
+
+/// Run `handler` and send its result back through `tx`.
+///
+/// A panic in the handler is reported to the caller as `ActorError::HandlerPanicked`,
+/// then propagated to the event loop so that the actor is stopped.
+pub(crate) async fn reply<F: Future>(
+    handler: F,
+    tx: oneshot::Sender<std::result::Result<F::Output, ActorError>>,
+) {
+    match AssertUnwindSafe(handler).catch_unwind().await {
+        Ok(res) => {
+            let _ = tx.send(Ok(res));
+        }
+        Err(payload) => {
+            let _ = tx.send(Err(ActorError::HandlerPanicked(panic_message(&*payload))));
+            panic::resume_unwind(payload);
         }
     }
 }
 
 pub struct WeakAddr<A> {
     pub(crate) actor_id: ActorId,
-    pub(crate) tx: Weak<mpsc::UnboundedSender<ActorEvent<A>>>,
-    pub(crate) rx_exit: Option<Shared<oneshot::Receiver<()>>>,
+    pub(crate) tx: Weak<Mailbox<A>>,
+    pub(crate) rx_exit: Option<ExitReceiver>,
 }
 
 impl<A> PartialEq for WeakAddr<A> {
@@ -1,10 +1,127 @@
-use crate::{Actor, ActorId, Addr, Context, Handler, Message, Result, Sender, Service};
+use crate::metrics;
+use crate::{Actor, ActorError, ActorId, Addr, Context, Handler, Message, Result, Sender, Service};
 use fnv::FnvHasher;
-use std::{collections::HashMap, hash::BuildHasherDefault};
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::BuildHasherDefault;
+use std::sync::Arc;
+
+/// The topic of a published message, or a pattern of topics in a `Subscription`.
+///
+/// Topics are made of segments separated by `.`, such as `orders.eu.created`. In a pattern, `*` matches exactly one
+/// segment and a final `#` matches any number of remaining segments, so `orders.*.created` and `orders.#` both match
+/// `orders.eu.created`. A pattern with `#` anywhere but in the last segment matches nothing.
+///
+/// Typed topics can be used by implementing `From<MyTopic> for Topic`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Topic(String);
+
+impl Topic {
+    /// Returns `true` if `topic` matches this topic, used as a pattern.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use xactor::Topic;
+    ///
+    /// let matches = |pattern: &str, topic: &str| Topic::from(pattern).matches(&Topic::from(topic));
+    /// assert!(matches("orders.*.created", "orders.eu.created"));
+    /// assert!(!matches("orders.*.created", "orders.created"));
+    /// assert!(matches("orders.#", "orders.eu.created"));
+    /// assert!(matches("orders.#", "orders"));
+    /// assert!(!matches("orders.#", "payments.eu"));
+    ///
+    /// // `#` is only a wildcard in the last segment
+    /// assert!(!matches("a.#.c", "a.x.y.z"));
+    /// assert!(!matches("a.#.c", "a.x.c"));
+    /// ```
+    pub fn matches(&self, topic: &Topic) -> bool {
+        let mut pattern = self.0.split('.');
+        let mut topic = topic.0.split('.');
+        loop {
+            match (pattern.next(), topic.next()) {
+                (Some("#"), _) => return pattern.next().is_none(),
+                (None, None) => return true,
+                (Some("*"), Some(_)) => {}
+                (Some(p), Some(t)) if p == t => {}
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl From<&str> for Topic {
+    fn from(topic: &str) -> Self {
+        Topic(topic.to_string())
+    }
+}
+
+impl From<String> for Topic {
+    fn from(topic: String) -> Self {
+        Topic(topic)
+    }
+}
+
+impl fmt::Display for Topic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Which messages published to a `Broker<T>` are delivered to a subscriber, see `Context::subscribe_with`.
+///
+/// By default, every message is delivered.
+pub struct Subscription<T> {
+    topic: Option<Topic>,
+    filter: Option<Box<dyn Fn(&T) -> bool + Send + Sync>>,
+}
+
+impl<T> Default for Subscription<T> {
+    fn default() -> Self {
+        Self {
+            topic: None,
+            filter: None,
+        }
+    }
+}
+
+impl<T> Subscription<T> {
+    /// Create a subscription to every message.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only deliver the messages published with `Addr::publish_to`, with a topic matching `pattern`.
+    pub fn topic(mut self, pattern: impl Into<Topic>) -> Self {
+        self.topic = Some(pattern.into());
+        self
+    }
+
+    /// Only deliver the messages for which `filter` returns `true`.
+    ///
+    /// The filter is evaluated by the broker, before the message is sent to the subscriber.
+    pub fn filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    fn accepts(&self, topic: Option<&Topic>, msg: &T) -> bool {
+        let topic_matches = match (&self.topic, topic) {
+            (None, _) => true,
+            (Some(pattern), Some(topic)) => pattern.matches(topic),
+            (Some(_), None) => false,
+        };
+        topic_matches && self.filter.as_ref().map_or(true, |filter| filter(msg))
+    }
+}
 
 pub(crate) struct Subscribe<T: Message<Result = ()>> {
     pub(crate) actor_id: ActorId,
     pub(crate) sender: Sender<T>,
+    pub(crate) subscription: Subscription<T>,
 }
 
 impl<T: Message<Result = ()>> Message for Subscribe<T> {
@@ -18,8 +135,26 @@ pub(crate) struct Unsubscribe {
 impl Message for Unsubscribe {
     type Result = ();
 }
+
+struct Publish<T> {
+    topic: Topic,
+    msg: T,
+}
+
+impl<T: Message<Result = ()>> Message for Publish<T> {
+    type Result = ();
+}
+
+struct Subscriber<T: Message<Result = ()>> {
+    actor_id: ActorId,
+    sender: Sender<T>,
+    subscription: Subscription<T>,
+}
+
 /// Message broker is used to support publishing and subscribing to messages.
 ///
+/// A subscriber whose bounded mailbox is full when a message is published misses that message, but stays subscribed.
+///
 /// # Examples
 ///
 /// ```rust
@@ -36,7 +171,6 @@ impl Message for Unsubscribe {
 /// #[derive(Default)]
 /// struct MyActor(String);
 ///
-/// #[async_trait::async_trait]
 /// impl Actor for MyActor {
 ///     async fn started(&mut self, ctx: &mut Context<Self>) -> Result<()>  {
 ///         ctx.subscribe::<MyMsg>().await;
@@ -44,14 +178,12 @@ impl Message for Unsubscribe {
 ///     }
 /// }
 ///
-/// #[async_trait::async_trait]
 /// impl Handler<MyMsg> for MyActor {
 ///     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: MyMsg) {
 ///         self.0 += msg.0;
 ///     }
 /// }
 ///
-/// #[async_trait::async_trait]
 /// impl Handler<GetValue> for MyActor {
 ///     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: GetValue) -> String {
 ///         self.0.clone()
@@ -74,7 +206,7 @@ impl Message for Unsubscribe {
 /// }
 /// ```
 pub struct Broker<T: Message<Result = ()>> {
-    subscribers: HashMap<ActorId, Sender<T>, BuildHasherDefault<FnvHasher>>,
+    subscribers: Vec<Subscriber<T>>,
 }
 
 impl<T: Message<Result = ()>> Default for Broker<T> {
@@ -89,29 +221,149 @@ impl<T: Message<Result = ()>> Actor for Broker<T> {}
 
 impl<T: Message<Result = ()>> Service for Broker<T> {}
 
+impl<T: Message<Result = ()>> Broker<T> {
+    /// Send `msg` to every subscriber accepting it, at most once per actor.
+    fn broadcast(&mut self, topic: Option<&Topic>, msg: T)
+    where
+        T: Clone,
+    {
+        let mut delivered = HashSet::<ActorId, BuildHasherDefault<FnvHasher>>::default();
+        // Remove the subscribers that have stopped. Those whose bounded mailbox is full miss this message,
+        // but stay subscribed.
+        self.subscribers.retain(|subscriber| {
+            if delivered.contains(&subscriber.actor_id)
+                || !subscriber.subscription.accepts(topic, &msg)
+            {
+                return true;
+            }
+            delivered.insert(subscriber.actor_id);
+            match subscriber.sender.send(msg.clone()) {
+                Ok(()) => true,
+                Err(err) => !matches!(
+                    err.downcast_ref::<ActorError>(),
+                    Some(ActorError::Disconnected)
+                ),
+            }
+        });
+        metrics::set_broker_subscribers::<T>(self.subscribers.len());
+    }
+}
+
 impl<T: Message<Result = ()>> Handler<Subscribe<T>> for Broker<T> {
     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: Subscribe<T>) {
-        self.subscribers.insert(msg.actor_id, msg.sender);
+        // Subscribing again to the same topic replaces the previous subscription
+        self.subscribers.retain(|subscriber| {
+            subscriber.actor_id != msg.actor_id
+                || subscriber.subscription.topic != msg.subscription.topic
+        });
+        self.subscribers.push(Subscriber {
+            actor_id: msg.actor_id,
+            sender: msg.sender,
+            subscription: msg.subscription,
+        });
+        metrics::set_broker_subscribers::<T>(self.subscribers.len());
     }
 }
 
 impl<T: Message<Result = ()>> Handler<Unsubscribe> for Broker<T> {
     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: Unsubscribe) {
-        self.subscribers.remove(&msg.actor_id);
+        self.subscribers
+            .retain(|subscriber| subscriber.actor_id != msg.actor_id);
+        metrics::set_broker_subscribers::<T>(self.subscribers.len());
     }
 }
 
 impl<T: Message<Result = ()> + Clone> Handler<T> for Broker<T> {
     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: T) {
-        // Broadcast to all subscribers and remove any senders that return an error (most likely because reciever dropped because actor already stopped)
-        self.subscribers
-            .retain(|_actor_id, sender| sender.send(msg.clone()).is_ok())
+        self.broadcast(None, msg);
+    }
+}
+
+impl<T: Message<Result = ()> + Clone> Handler<Publish<T>> for Broker<T> {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: Publish<T>) {
+        self.broadcast(Some(&msg.topic), msg.msg);
     }
 }
 
 impl<T: Message<Result = ()> + Clone> Addr<Broker<T>> {
     /// Publishes a message of the specified type.
+    ///
+    /// It is only delivered to the subscriptions without a topic.
     pub fn publish(&mut self, msg: T) -> Result<()> {
         self.send(msg)
     }
+
+    /// Publishes a message of the specified type on `topic`.
+    ///
+    /// It is delivered to the subscriptions without a topic, and to those with a topic pattern matching `topic`.
+    pub fn publish_to(&mut self, topic: impl Into<Topic>, msg: T) -> Result<()> {
+        self.send(Publish {
+            topic: topic.into(),
+            msg,
+        })
+    }
+}
+
+impl<T: Message<Result = ()> + Sync> Addr<Broker<Arc<T>>> {
+    /// Publishes a message wrapped in an `Arc`, which is shared by the subscribers instead of being cloned for each
+    /// of them, so `T` does not need to implement `Clone`.
+    ///
+    /// The subscribers handle and subscribe to `Arc<T>`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use xactor::*;
+    ///
+    /// #[message]
+    /// struct Frame(Vec<u8>);
+    ///
+    /// #[message(result = "usize")]
+    /// struct GetReceived;
+    ///
+    /// #[derive(Default)]
+    /// struct Recorder(usize);
+    ///
+    /// impl Actor for Recorder {
+    ///     async fn started(&mut self, ctx: &mut Context<Self>) -> Result<()> {
+    ///         ctx.subscribe::<Arc<Frame>>().await
+    ///     }
+    /// }
+    ///
+    /// impl Handler<Arc<Frame>> for Recorder {
+    ///     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: Arc<Frame>) {
+    ///         self.0 += msg.0.len();
+    ///     }
+    /// }
+    ///
+    /// impl Handler<GetReceived> for Recorder {
+    ///     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: GetReceived) -> usize {
+    ///         self.0
+    ///     }
+    /// }
+    ///
+    /// #[xactor::main]
+    /// async fn main() -> Result<()> {
+    ///     let addr1 = Recorder::start_default().await?;
+    ///     let addr2 = Recorder::start_default().await?;
+    ///
+    ///     let mut broker = Broker::from_registry().await?;
+    ///     broker.publish_shared(Frame(vec![0; 1024]))?;
+    ///     // Once the broker has handled it, the frame is queued before the calls
+    ///     testing::drain(&broker).await;
+    ///
+    ///     assert_eq!(addr1.call(GetReceived).await?, 1024);
+    ///     assert_eq!(addr2.call(GetReceived).await?, 1024);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn publish_shared(&mut self, msg: T) -> Result<()> {
+        self.publish(Arc::new(msg))
+    }
+
+    /// Publishes a message wrapped in an `Arc` on `topic`, like `publish_shared`.
+    pub fn publish_shared_to(&mut self, topic: impl Into<Topic>, msg: T) -> Result<()> {
+        self.publish_to(topic, Arc::new(msg))
+    }
 }
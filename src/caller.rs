@@ -1,13 +1,14 @@
+use crate::runtime;
 use crate::{ActorId, Message, Result};
 use std::future::Future;
 use std::hash::{Hash, Hasher};
 use std::pin::Pin;
+use std::time::Duration;
 
 /// Caller of a specific message type
 ///
 /// Like `Sender<T>`, Caller has a weak reference to the recipient of the message type, and so will not prevent an actor from stopping if all Addr's have been dropped elsewhere.
 /// This takes a boxed closure with the message as a parameter with the mpsc channel of the actor inside and the type of actor abstracted away.
-
 pub struct Caller<T: Message> {
     pub actor_id: ActorId,
     pub(crate) caller_fn: Box<dyn CallerFn<T>>,
@@ -17,6 +18,11 @@ impl<T: Message> Caller<T> {
     pub async fn call(&self, msg: T) -> Result<T::Result> {
         self.caller_fn.call(msg).await
     }
+
+    /// Like `call`, but fails with `ActorError::Timeout` if the reply does not arrive within `timeout`.
+    pub async fn call_timeout(&self, msg: T, timeout: Duration) -> Result<T::Result> {
+        runtime::timeout(timeout, self.caller_fn.call(msg)).await?
+    }
 }
 
 impl<T: Message> PartialEq for Caller<T> {
@@ -44,7 +50,6 @@ impl<T: Message> Clone for Caller<T> {
 ///
 /// Like `Caller<T>`, Sender has a weak reference to the recipient of the message type, and so will not prevent an actor from stopping if all Addr's have been dropped elsewhere.
 /// This allows it to be used in `send_later` `send_interval` actor functions, and not keep the actor alive indefinitely even after all references to it have been dropped (unless `ctx.stop()` is called from within)
-
 pub struct Sender<T: Message> {
     pub actor_id: ActorId,
     pub(crate) sender_fn: Box<dyn SenderFn<T>>,
@@ -102,15 +107,19 @@ pub trait CallerFn<T>: DynClone + 'static + Send + Sync
 where
     T: Message,
 {
-    fn call(&self, msg: T) -> Pin<Box<dyn Future<Output = Result<T::Result>>>>;
+    fn call(&self, msg: T) -> Pin<Box<dyn Future<Output = Result<T::Result>> + Send>>;
 }
 
 impl<F, T> CallerFn<T> for F
 where
-    F: Fn(T) -> Pin<Box<dyn Future<Output = Result<T::Result>>>> + 'static + Send + Sync + Clone,
+    F: Fn(T) -> Pin<Box<dyn Future<Output = Result<T::Result>> + Send>>
+        + 'static
+        + Send
+        + Sync
+        + Clone,
     T: Message,
 {
-    fn call(&self, msg: T) -> Pin<Box<dyn Future<Output = Result<T::Result>>>> {
+    fn call(&self, msg: T) -> Pin<Box<dyn Future<Output = Result<T::Result>> + Send>> {
         self(msg)
     }
 }
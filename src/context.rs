@@ -1,9 +1,12 @@
-use crate::addr::ActorEvent;
+use crate::addr::{is_alive, send_event, ActorEvent, ExitReceiver};
 use crate::broker::{Subscribe, Unsubscribe};
+use crate::mailbox::{mailbox, Mailbox, MailboxReceiver};
 use crate::runtime::{sleep, spawn};
-use crate::{ActorId, Addr, Broker, Error, Handler, Message, Result, Service, StreamHandler};
-use futures::channel::{mpsc, oneshot};
-use futures::future::{AbortHandle, Abortable, Shared};
+use crate::{
+    Actor, ActorBuilder, ActorError, ActorId, Addr, Broker, Error, ExitReason, Handler, Message,
+    Result, Service, StreamHandler, Subscription, Terminated,
+};
+use futures::future::{AbortHandle, Abortable};
 use futures::{Stream, StreamExt};
 use once_cell::sync::OnceCell;
 use slab::Slab;
@@ -13,13 +16,35 @@ use std::sync::Mutex;
 use std::sync::{Arc, Weak};
 use std::time::Duration;
 
+/// Allocate a new actor id.
+pub(crate) fn next_actor_id() -> ActorId {
+    static ACTOR_ID: OnceCell<AtomicUsize> = OnceCell::new();
+    ACTOR_ID
+        .get_or_init(Default::default)
+        .fetch_add(1, Ordering::Relaxed)
+}
+
 ///An actor execution context.
 pub struct Context<A> {
     actor_id: ActorId,
-    tx: Weak<mpsc::UnboundedSender<ActorEvent<A>>>,
-    pub(crate) rx_exit: Option<Shared<oneshot::Receiver<()>>>,
+    tx: Weak<Mailbox<A>>,
+    pub(crate) rx_exit: Option<ExitReceiver>,
     pub(crate) streams: Arc<Mutex<Slab<AbortHandle>>>,
     pub(crate) intervals: Arc<Mutex<Slab<AbortHandle>>>,
+    children: Vec<Child>,
+}
+
+/// A child actor started with `Context::spawn_child`, with its type erased.
+struct Child {
+    actor_id: ActorId,
+    stop: Box<dyn Fn() + Send + Sync>,
+    rx_exit: Option<ExitReceiver>,
+}
+
+impl Child {
+    fn is_alive(&self) -> bool {
+        is_alive(self.rx_exit.as_ref())
+    }
 }
 
 impl<A> fmt::Debug for Context<A> {
@@ -32,20 +57,11 @@ impl<A> fmt::Debug for Context<A> {
 
 impl<A> Context<A> {
     pub(crate) fn new(
-        rx_exit: Option<Shared<oneshot::Receiver<()>>>,
-    ) -> (
-        Self,
-        mpsc::UnboundedReceiver<ActorEvent<A>>,
-        Arc<mpsc::UnboundedSender<ActorEvent<A>>>,
-    ) {
-        static ACTOR_ID: OnceCell<AtomicUsize> = OnceCell::new();
-
-        // Get an actor id
-        let actor_id = ACTOR_ID
-            .get_or_init(Default::default)
-            .fetch_add(1, Ordering::Relaxed);
-
-        let (tx, rx) = mpsc::unbounded::<ActorEvent<A>>();
+        rx_exit: Option<ExitReceiver>,
+        builder: &ActorBuilder,
+    ) -> (Self, MailboxReceiver<A>, Arc<Mailbox<A>>) {
+        let actor_id = next_actor_id();
+        let (tx, rx) = mailbox::<A>(builder, actor_id);
         let tx = Arc::new(tx);
         let weak_tx = Arc::downgrade(&tx);
         (
@@ -55,6 +71,7 @@ impl<A> Context<A> {
                 rx_exit,
                 streams: Default::default(),
                 intervals: Default::default(),
+                children: Vec::new(),
             },
             rx,
             tx,
@@ -79,9 +96,7 @@ impl<A> Context<A> {
     /// Stop the actor.
     pub fn stop(&self, err: Option<Error>) {
         if let Some(tx) = self.tx.upgrade() {
-            mpsc::UnboundedSender::clone(&*tx)
-                .start_send(ActorEvent::Stop(err))
-                .ok();
+            tx.send_event(ActorEvent::Stop(err)).ok();
         }
     }
 
@@ -115,7 +130,6 @@ impl<A> Context<A> {
     /// #[derive(Default)]
     /// struct MyActor(i32);
     ///
-    /// #[async_trait::async_trait]
     /// impl StreamHandler<i32> for MyActor {
     ///     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: i32) {
     ///         self.0 += msg;
@@ -130,14 +144,12 @@ impl<A> Context<A> {
     ///     }
     /// }
     ///
-    /// #[async_trait::async_trait]
     /// impl Handler<GetSum> for MyActor {
     ///     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: GetSum) -> i32 {
     ///         self.0
     ///     }
     /// }
     ///
-    /// #[async_trait::async_trait]
     /// impl Actor for MyActor {
     ///     async fn started(&mut self, ctx: &mut Context<Self>) -> Result<()> {
     ///         let values = (0..100).collect::<Vec<_>>();
@@ -173,26 +185,26 @@ impl<A> Context<A> {
         let fut = {
             async move {
                 if let Some(tx) = tx.upgrade() {
-                    mpsc::UnboundedSender::clone(&*tx)
-                        .start_send(ActorEvent::Exec(Box::new(move |actor, ctx| {
-                            Box::pin(async move {
-                                StreamHandler::started(actor, ctx).await;
-                            })
-                        })))
-                        .ok();
+                    tx.send(Box::new(move |actor, ctx| {
+                        Box::pin(async move {
+                            StreamHandler::started(actor, ctx).await;
+                        })
+                    }))
+                    .await
+                    .ok();
                 } else {
                     return;
                 }
 
                 while let Some(msg) = stream.next().await {
                     if let Some(tx) = tx.upgrade() {
-                        let res = mpsc::UnboundedSender::clone(&*tx).start_send(ActorEvent::Exec(
-                            Box::new(move |actor, ctx| {
+                        let res = tx
+                            .send(Box::new(move |actor, ctx| {
                                 Box::pin(async move {
                                     StreamHandler::handle(actor, ctx, msg).await;
                                 })
-                            }),
-                        ));
+                            }))
+                            .await;
                         if res.is_err() {
                             return;
                         }
@@ -202,19 +214,17 @@ impl<A> Context<A> {
                 }
 
                 if let Some(tx) = tx.upgrade() {
-                    mpsc::UnboundedSender::clone(&*tx)
-                        .start_send(ActorEvent::Exec(Box::new(move |actor, ctx| {
-                            Box::pin(async move {
-                                StreamHandler::finished(actor, ctx).await;
-                            })
-                        })))
-                        .ok();
+                    tx.send(Box::new(move |actor, ctx| {
+                        Box::pin(async move {
+                            StreamHandler::finished(actor, ctx).await;
+                        })
+                    }))
+                    .await
+                    .ok();
                 }
 
                 if let Some(tx) = tx.upgrade() {
-                    mpsc::UnboundedSender::clone(&*tx)
-                        .start_send(ActorEvent::RemoveStream(id))
-                        .ok();
+                    tx.send_event(ActorEvent::RemoveStream(id)).ok();
                 }
             }
         };
@@ -223,8 +233,9 @@ impl<A> Context<A> {
 
     /// Sends the message `msg` to self after a specified period of time.
     ///
+    /// If the actor has a bounded mailbox that is full when the delay elapses, the message is dropped.
+    ///
     /// We use `Sender` instead of `Addr` so that the interval doesn't keep reference to address and prevent the actor from being dropped and stopped
-
     pub fn send_later<T>(&mut self, msg: T, after: Duration) -> AbortHandle
     where
         A: Handler<T>,
@@ -241,9 +252,10 @@ impl<A> Context<A> {
         let (handle, registration) = futures::future::AbortHandle::new_pair();
         entry.insert(handle.clone());
 
+        let sleep = sleep(after);
         spawn(Abortable::new(
             async move {
-                sleep(after).await;
+                sleep.await;
                 sender.send(msg).ok();
                 // We have to remove the entry after the send has been completed or the slab will grow indefinitely
                 let mut intervals = intervals_clone.lock().unwrap();
@@ -256,6 +268,8 @@ impl<A> Context<A> {
 
     /// Sends the message  to self, at a specified fixed interval.
     /// The message is created each time using a closure `f`.
+    ///
+    /// If the actor has a bounded mailbox that is full when the interval elapses, that tick is skipped.
     pub fn send_interval_with<T, F>(&mut self, f: F, dur: Duration) -> AbortHandle
     where
         A: Handler<T>,
@@ -273,16 +287,24 @@ impl<A> Context<A> {
         let (handle, registration) = futures::future::AbortHandle::new_pair();
         entry.insert(handle.clone());
 
+        let mut tick = sleep(dur);
         spawn(Abortable::new(
             async move {
                 loop {
-                    sleep(dur).await;
-                    if sender.send(f()).is_err() {
+                    tick.await;
+                    let disconnected = sender.send(f()).is_err_and(|err| {
+                        matches!(
+                            err.downcast_ref::<ActorError>(),
+                            Some(ActorError::Disconnected)
+                        )
+                    });
+                    if disconnected {
                         // Again, we have to remove the entry after the send has been completed or the slab will grow indefinitely
                         let mut intervals = intervals_clone.lock().unwrap();
                         intervals.remove(key);
                         break;
                     }
+                    tick = sleep(dur);
                 }
             },
             registration,
@@ -299,17 +321,336 @@ impl<A> Context<A> {
         self.send_interval_with(move || msg.clone(), dur)
     }
 
+    /// Watch the actor at `addr`, and receive a `Terminated` message once it has stopped.
+    ///
+    /// The `Terminated` message is delivered even if the mailbox of this actor is full.
+    /// The monitor is cancelled when this actor stops, or when the returned handle is aborted.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use xactor::testing::TestProbe;
+    /// use xactor::*;
+    ///
+    /// struct Worker;
+    ///
+    /// impl Actor for Worker {}
+    ///
+    /// struct Watcher {
+    ///     worker: Addr<Worker>,
+    ///     terminated: Sender<Terminated>,
+    /// }
+    ///
+    /// impl Actor for Watcher {
+    ///     async fn started(&mut self, ctx: &mut Context<Self>) -> Result<()> {
+    ///         ctx.monitor(&self.worker);
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// impl Handler<Terminated> for Watcher {
+    ///     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: Terminated) {
+    ///         self.terminated.send(msg).ok();
+    ///     }
+    /// }
+    ///
+    /// #[xactor::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut worker = Worker.start().await?;
+    ///     let mut terminated = TestProbe::new();
+    ///     let _watcher = Watcher {
+    ///         worker: worker.clone(),
+    ///         terminated: terminated.sender(),
+    ///     }
+    ///     .start()
+    ///     .await?;
+    ///
+    ///     worker.stop(None)?;
+    ///     let msg = terminated.expect_msg().await;
+    ///     assert_eq!(msg.actor_id, worker.actor_id());
+    ///     assert!(matches!(msg.reason, ExitReason::Normal));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn monitor<B>(&mut self, addr: &Addr<B>) -> AbortHandle
+    where
+        A: Handler<Terminated>,
+    {
+        let tx = self.tx.clone();
+        let actor_id = addr.actor_id;
+        let rx_exit = addr.rx_exit.clone();
+
+        self.spawn_watch(rx_exit, move |reason| {
+            // Like `Stop`, bypass the capacity limit so that the notification is never lost
+            if let Some(tx) = tx.upgrade() {
+                tx.send_event(ActorEvent::Notify(send_event(Terminated {
+                    actor_id,
+                    reason,
+                })))
+                .ok();
+            }
+        })
+    }
+
+    /// Link this actor with the actor at `addr`.
+    ///
+    /// If either actor fails (stops with an error or panics), the other one is stopped with an `ActorError::LinkFailed` error.
+    /// Actors that stop normally, or because all their addresses were dropped, do not affect each other.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use xactor::*;
+    ///
+    /// struct Worker;
+    ///
+    /// impl Actor for Worker {}
+    ///
+    /// struct Manager(Addr<Worker>);
+    ///
+    /// impl Actor for Manager {
+    ///     async fn started(&mut self, ctx: &mut Context<Self>) -> Result<()> {
+    ///         ctx.link(&self.0);
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// #[xactor::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut worker = Worker.start().await?;
+    ///     let manager = Manager(worker.clone()).start().await?;
+    ///
+    ///     worker.stop(Some(error::anyhow!("crashed")))?;
+    ///
+    ///     let reason = manager.wait_for_stop().await;
+    ///     match reason.error().unwrap().downcast_ref::<ActorError>() {
+    ///         Some(ActorError::LinkFailed { actor_id, .. }) => assert_eq!(*actor_id, worker.actor_id()),
+    ///         err => panic!("unexpected error: {:?}", err),
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn link<B: Actor>(&mut self, addr: &Addr<B>)
+    where
+        A: Actor,
+    {
+        // The other actor failed, stop this one
+        let tx = self.tx.clone();
+        let actor_id = addr.actor_id;
+        self.spawn_watch(addr.rx_exit.clone(), move |reason| {
+            if reason.is_failure() {
+                if let Some(tx) = tx.upgrade() {
+                    let err = ActorError::LinkFailed { actor_id, reason };
+                    tx.send_event(ActorEvent::Stop(Some(err.into()))).ok();
+                }
+            }
+        });
+
+        // This actor failed, stop the other one
+        let other = addr.downgrade();
+        let actor_id = self.actor_id;
+        if let Some(rx_exit) = self.rx_exit.clone() {
+            spawn(async move {
+                if let Ok(reason) = rx_exit.await {
+                    if reason.is_failure() {
+                        if let Some(mut other) = other.upgrade() {
+                            other
+                                .stop(Some(ActorError::LinkFailed { actor_id, reason }.into()))
+                                .ok();
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    fn spawn_watch<F>(&mut self, rx_exit: Option<ExitReceiver>, f: F) -> AbortHandle
+    where
+        F: FnOnce(ExitReason) + Send + 'static,
+    {
+        let intervals_clone = self.intervals.clone();
+
+        let mut intervals = self.intervals.lock().unwrap();
+
+        let entry = intervals.vacant_entry();
+        let key = entry.key();
+
+        let (handle, registration) = futures::future::AbortHandle::new_pair();
+        entry.insert(handle.clone());
+
+        spawn(Abortable::new(
+            async move {
+                let reason = match rx_exit {
+                    Some(rx_exit) => rx_exit.await.unwrap_or(ExitReason::Panicked),
+                    None => futures::future::pending().await,
+                };
+                f(reason);
+                let mut intervals = intervals_clone.lock().unwrap();
+                intervals.remove(key);
+            },
+            registration,
+        ));
+        handle
+    }
+
+    /// Start a new child actor, returning its address.
+    ///
+    /// Children are stopped, in reverse order of their creation, when this actor stops.
+    /// This actor's `stopped` method is only called once all of its children have stopped.
+    ///
+    /// Like any other actor, a child also stops when all references to its `Addr` are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use xactor::*;
+    ///
+    /// struct Child;
+    ///
+    /// impl Actor for Child {}
+    ///
+    /// #[message(result = "Addr<Child>")]
+    /// struct SpawnChild;
+    ///
+    /// struct Parent(Vec<Addr<Child>>);
+    ///
+    /// impl Actor for Parent {}
+    ///
+    /// impl Handler<SpawnChild> for Parent {
+    ///     async fn handle(&mut self, ctx: &mut Context<Self>, _msg: SpawnChild) -> Addr<Child> {
+    ///         let child = ctx.spawn_child(Child).await.unwrap();
+    ///         self.0.push(child.clone());
+    ///         child
+    ///     }
+    /// }
+    ///
+    /// #[xactor::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut parent = Parent(Vec::new()).start().await?;
+    ///     let child = parent.call(SpawnChild).await?;
+    ///
+    ///     parent.stop(None)?;
+    ///     parent.wait_for_stop().await;
+    ///
+    ///     // The child has been stopped along with its parent
+    ///     assert!(matches!(child.wait_for_stop().await, ExitReason::Normal));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn spawn_child<B: Actor>(&mut self, actor: B) -> Result<Addr<B>> {
+        let addr = actor.start().await?;
+        let weak_addr = addr.downgrade();
+
+        self.children.retain(Child::is_alive);
+        self.children.push(Child {
+            actor_id: addr.actor_id,
+            stop: Box::new(move || {
+                if let Some(mut addr) = weak_addr.upgrade() {
+                    addr.stop(None).ok();
+                }
+            }),
+            rx_exit: addr.rx_exit.clone(),
+        });
+        Ok(addr)
+    }
+
+    /// Returns the ids of the child actors that are still running.
+    pub fn children(&self) -> Vec<ActorId> {
+        self.children
+            .iter()
+            .filter(|child| child.is_alive())
+            .map(|child| child.actor_id)
+            .collect()
+    }
+
+    /// Stop all child actors and wait for them to finish.
+    pub(crate) async fn stop_children(&mut self) {
+        while let Some(child) = self.children.pop() {
+            (child.stop)();
+            if let Some(rx_exit) = child.rx_exit {
+                rx_exit.await.ok();
+            }
+        }
+    }
+
     /// Subscribes to a message of a specified type.
     pub async fn subscribe<T: Message<Result = ()>>(&self) -> Result<()>
     where
         A: Handler<T>,
     {
-        let broker = Broker::<T>::default().start_service().await?;
+        self.subscribe_with(Subscription::new()).await
+    }
+
+    /// Subscribes to the messages of a specified type accepted by `subscription`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use xactor::*;
+    ///
+    /// #[message]
+    /// #[derive(Clone)]
+    /// struct Order {
+    ///     amount: u32,
+    /// }
+    ///
+    /// #[message(result = "Vec<u32>")]
+    /// struct GetAmounts;
+    ///
+    /// #[derive(Default)]
+    /// struct LargeEuOrders(Vec<u32>);
+    ///
+    /// impl Actor for LargeEuOrders {
+    ///     async fn started(&mut self, ctx: &mut Context<Self>) -> Result<()> {
+    ///         let subscription = Subscription::new()
+    ///             .topic("orders.eu.*")
+    ///             .filter(|order: &Order| order.amount >= 100);
+    ///         ctx.subscribe_with(subscription).await
+    ///     }
+    /// }
+    ///
+    /// impl Handler<Order> for LargeEuOrders {
+    ///     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: Order) {
+    ///         self.0.push(msg.amount);
+    ///     }
+    /// }
+    ///
+    /// impl Handler<GetAmounts> for LargeEuOrders {
+    ///     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: GetAmounts) -> Vec<u32> {
+    ///         self.0.clone()
+    ///     }
+    /// }
+    ///
+    /// #[xactor::main]
+    /// async fn main() -> Result<()> {
+    ///     let addr = LargeEuOrders::start_default().await?;
+    ///
+    ///     let mut broker = Broker::from_registry().await?;
+    ///     broker.publish_to("orders.eu.paris", Order { amount: 150 })?;
+    ///     broker.publish_to("orders.eu.paris", Order { amount: 20 })?;
+    ///     broker.publish_to("orders.us.boston", Order { amount: 300 })?;
+    ///     broker.publish(Order { amount: 500 })?;
+    ///     // Once the broker has handled them, the orders are queued before the call
+    ///     testing::drain(&broker).await;
+    ///
+    ///     assert_eq!(addr.call(GetAmounts).await?, [150]);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn subscribe_with<T: Message<Result = ()>>(
+        &self,
+        subscription: Subscription<T>,
+    ) -> Result<()>
+    where
+        A: Handler<T>,
+    {
+        let broker = Broker::<T>::from_registry().await?;
         let sender = self.address().sender();
         broker
             .send(Subscribe {
                 actor_id: self.actor_id,
                 sender,
+                subscription,
             })
             .ok();
         Ok(())
@@ -317,7 +658,7 @@ impl<A> Context<A> {
 
     /// Unsubscribe to a message of a specified type.
     pub async fn unsubscribe<T: Message<Result = ()>>(&self) -> Result<()> {
-        let broker = Broker::<T>::default().start_service().await?;
+        let broker = Broker::<T>::from_registry().await?;
         broker.send(Unsubscribe {
             actor_id: self.actor_id,
         })
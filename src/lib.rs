@@ -1,10 +1,10 @@
-//! # Xactor is a rust actors framework based on async-std
+//! # Xactor is a rust actors framework based on tokio, async-std or smol
 //!
 //! ## Documentation
 //!
 //! * [GitHub repository](https://github.com/sunli829/xactor)
 //! * [Cargo package](https://crates.io/crates/xactor)
-//! * Minimum supported Rust version: 1.39 or later
+//! * Minimum supported Rust version: 1.75 or later
 //!
 //! ## Features
 //!
@@ -12,6 +12,11 @@
 //! * Actor communication in a local context.
 //! * Using Futures for asynchronous message handling.
 //! * Typed messages (No `Any` type). Generic messages are allowed.
+//! * Single-threaded actors with `!Send` state, see `LocalActor`.
+//! * Optional metrics for every actor, with the `metrics` feature.
+//! * Optional tracing spans for every handled message, with the `tracing` feature.
+//! * Opt-in listing of the running actors, with their name, mailbox length and status, see `System::actors`.
+//! * Runs on tokio (default), async-std or smol, selected with the `runtime-tokio`, `runtime-async-std` or `runtime-smol` feature.
 //!
 //! ## Examples
 //!
@@ -25,7 +30,6 @@
 //!
 //! impl Actor for MyActor {}
 //!
-//! #[async_trait::async_trait]
 //! impl Handler<ToUppercase> for MyActor {
 //!     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: ToUppercase) -> String {
 //!         msg.0.to_uppercase()
@@ -44,6 +48,25 @@
 //! }
 //! ```
 //!
+//! ## Metrics
+//!
+//! With the `metrics` feature, xactor records the following through the [metrics](https://crates.io/crates/metrics)
+//! facade, for any installed recorder:
+//!
+//! |Name|Type|Labels|
+//! |----|----|------|
+//! |`xactor_mailbox_length`|gauge|`actor_type`, `actor_id`|
+//! |`xactor_messages_processed_total`|counter|`actor_type`, `actor_id`, `message_type`|
+//! |`xactor_handler_duration_seconds`|histogram|`actor_type`, `actor_id`, `message_type`|
+//! |`xactor_supervisor_restarts_total`|counter|`actor_type`, `actor_id`|
+//! |`xactor_broker_subscribers`|gauge|`message_type`|
+//!
+//! ## Tracing
+//!
+//! With the `tracing` feature, each message is handled in a `handle` span with the `actor_type`, `actor_id` and
+//! `message_type` fields, whose parent is the span that was current when the message was sent. Actors also emit
+//! `actor started`, `actor stopped` and `actor restarted` events.
+//!
 //! ## Performance
 //!
 //! **Actix vs. Xactor**
@@ -58,18 +81,30 @@
 //! ## References
 //!
 //! * [Actix](https://github.com/actix/actix)
+//! * [Tokio](https://github.com/tokio-rs/tokio)
 //! * [Async-std](https://github.com/async-rs/async-std)
+//! * [Smol](https://github.com/smol-rs/smol)
 
 #![allow(clippy::type_complexity)]
 
 mod actor;
+mod actor_error;
 mod addr;
 mod broker;
 mod caller;
 mod context;
+mod local;
+mod mailbox;
+mod metrics;
+mod monitor;
+mod registry;
 mod runtime;
 mod service;
 mod supervisor;
+mod supervisor_group;
+mod system;
+pub mod testing;
+mod trace;
 
 #[cfg(all(feature = "anyhow", feature = "eyre"))]
 compile_error!(
@@ -93,12 +128,22 @@ pub type Error = error::Error;
 
 pub type ActorId = usize;
 
-pub use actor::{Actor, Handler, Message, StreamHandler};
-pub use addr::{Addr, WeakAddr};
-pub use broker::Broker;
+pub use actor::{Actor, ActorBuilder, Handler, Message, StreamHandler};
+pub use actor_error::ActorError;
+pub use addr::{Addr, ExitReason, WeakAddr};
+pub use broker::{Broker, Subscription, Topic};
 pub use caller::{Caller, Sender};
 pub use context::Context;
-pub use runtime::{block_on, sleep, spawn, timeout};
-pub use service::{LocalService, Service};
-pub use supervisor::Supervisor;
+pub use local::{LocalActor, LocalAddr, LocalContext, LocalHandler};
+pub use monitor::Terminated;
+pub use registry::{Registration, Registry};
+#[cfg(feature = "runtime-tokio")]
+pub use runtime::set_runtime_handle;
+pub use runtime::{
+    block_on, block_on_with, sleep, spawn, spawn_local, timeout, JoinHandle, RuntimeOptions,
+};
+pub use service::{Dependency, KeyedService, LocalService, Service};
+pub use supervisor::{Supervisor, SupervisorStrategy};
+pub use supervisor_group::{RestartPolicy, SupervisorGroup};
+pub use system::{ActorInfo, ActorStatus, System};
 pub use xactor_derive::{main, message, Actor, Service};
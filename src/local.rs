@@ -0,0 +1,390 @@
+use crate::actor::run_handler;
+use crate::addr::{is_alive, reply, ExitReason, ExitReceiver};
+use crate::context::next_actor_id;
+use crate::mailbox::MailboxStats;
+use crate::runtime::{self, spawn_local};
+use crate::system;
+use crate::trace::{self, SenderSpan};
+use crate::{ActorError, ActorId, Caller, Error, Message, Result, Sender};
+use futures::channel::{mpsc, oneshot};
+use futures::{Future, FutureExt, StreamExt};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+type LocalExecFuture<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+type LocalExecFn<A> = Box<
+    dyn for<'a> FnOnce(&'a mut A, &'a mut LocalContext<A>) -> LocalExecFuture<'a> + Send + 'static,
+>;
+
+enum LocalEvent<A> {
+    Exec(LocalExecFn<A>),
+    Stop(Option<Error>),
+}
+
+struct LocalSender<A> {
+    tx: mpsc::UnboundedSender<LocalEvent<A>>,
+    stats: Arc<MailboxStats>,
+}
+
+impl<A> LocalSender<A> {
+    fn send_event(&self, event: LocalEvent<A>) -> Result<()> {
+        let is_message = matches!(event, LocalEvent::Exec(_));
+        if is_message {
+            self.stats.push();
+        }
+        if self.tx.unbounded_send(event).is_err() {
+            if is_message {
+                self.stats.cancel();
+            }
+            return Err(ActorError::Disconnected.into());
+        }
+        Ok(())
+    }
+}
+
+/// Describes how to handle messages of a specific type for a `LocalActor`.
+pub trait LocalHandler<T: Message>: LocalActor {
+    /// Method is called for every message received by this actor.
+    fn handle(&mut self, ctx: &mut LocalContext<Self>, msg: T) -> impl Future<Output = T::Result>;
+}
+
+/// An actor whose state does not need to be `Send`.
+///
+/// A local actor runs on the thread that started it, so it can hold `Rc`, `RefCell` and other
+/// thread-bound state, and its handlers can await futures that are not `Send`.
+/// Its address is still `Send`: messages can be sent to it from any thread or actor.
+///
+/// Local actors must be started from a thread running `xactor::block_on` (or `#[xactor::main]`),
+/// or, with tokio, from inside a `tokio::task::LocalSet`.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+/// use xactor::*;
+///
+/// #[message(result = "usize")]
+/// struct Push(String);
+///
+/// struct MyActor {
+///     items: Rc<RefCell<Vec<String>>>,
+/// }
+///
+/// impl LocalActor for MyActor {}
+///
+/// impl LocalHandler<Push> for MyActor {
+///     async fn handle(&mut self, _ctx: &mut LocalContext<Self>, msg: Push) -> usize {
+///         self.items.borrow_mut().push(msg.0);
+///         self.items.borrow().len()
+///     }
+/// }
+///
+/// #[xactor::main]
+/// async fn main() -> Result<()> {
+///     let items = Rc::new(RefCell::new(Vec::new()));
+///     let addr = MyActor { items: items.clone() }.start().await?;
+///
+///     // The address can be used from other threads
+///     let other = addr.clone();
+///     spawn(async move { other.call(Push("a".to_string())).await.unwrap() }).await;
+///     assert_eq!(addr.call(Push("b".to_string())).await?, 2);
+///     assert_eq!(*items.borrow(), vec!["a", "b"]);
+///     Ok(())
+/// }
+/// ```
+#[allow(unused_variables)]
+pub trait LocalActor: Sized + 'static {
+    /// Called when the actor is first started.
+    fn started(&mut self, ctx: &mut LocalContext<Self>) -> impl Future<Output = Result<()>> {
+        async { Ok(()) }
+    }
+
+    /// Called after an actor is stopped.
+    fn stopped(&mut self, ctx: &mut LocalContext<Self>) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Start the actor on the current thread, returning its address.
+    fn start(self) -> impl Future<Output = Result<LocalAddr<Self>>> {
+        start_local_actor(self)
+    }
+}
+
+async fn start_local_actor<A: LocalActor>(mut actor: A) -> Result<LocalAddr<A>> {
+    let (tx_exit, rx_exit) = oneshot::channel();
+    let rx_exit = rx_exit.shared();
+    let actor_id = next_actor_id();
+    let (tx, mut rx) = mpsc::unbounded();
+    let stats = Arc::new(MailboxStats::new::<A>(actor_id));
+    let tx = Arc::new(LocalSender {
+        tx,
+        stats: stats.clone(),
+    });
+    let mut ctx = LocalContext {
+        actor_id,
+        tx: Arc::downgrade(&tx),
+        rx_exit: rx_exit.clone(),
+    };
+
+    actor.started(&mut ctx).await?;
+
+    let addr = LocalAddr {
+        actor_id: ctx.actor_id,
+        tx,
+        rx_exit: rx_exit.clone(),
+    };
+    let weak_tx = Arc::downgrade(&addr.tx);
+    let seq = system::register_with::<A>(
+        addr.actor_id,
+        None,
+        stats.clone(),
+        Arc::new(move || {
+            if let Some(tx) = weak_tx.upgrade() {
+                tx.send_event(LocalEvent::Stop(None)).ok();
+            }
+        }),
+        Some(rx_exit),
+    );
+    trace::started::<A>(addr.actor_id);
+
+    spawn_local(async move {
+        let exit_reason = loop {
+            match rx.next().await {
+                Some(LocalEvent::Exec(f)) => {
+                    stats.start_handling();
+                    let res = AssertUnwindSafe(f(&mut actor, &mut ctx))
+                        .catch_unwind()
+                        .await;
+                    stats.finish_handling();
+                    if res.is_err() {
+                        break ExitReason::Panicked;
+                    }
+                }
+                Some(LocalEvent::Stop(err)) => break ExitReason::from_stop(err),
+                None => break ExitReason::AllAddressesDropped,
+            }
+        };
+        stats.set_stopping();
+        rx.close();
+        while let Ok(event) = rx.try_recv() {
+            if let LocalEvent::Exec(_) = event {
+                stats.cancel();
+            }
+        }
+        actor.stopped(&mut ctx).await;
+        trace::stopped::<A>(ctx.actor_id, &exit_reason);
+        system::unregister(seq, &exit_reason);
+        tx_exit.send(exit_reason).ok();
+    });
+
+    Ok(addr)
+}
+
+/// The execution context of a `LocalActor`.
+pub struct LocalContext<A> {
+    actor_id: ActorId,
+    tx: Weak<LocalSender<A>>,
+    rx_exit: ExitReceiver,
+}
+
+impl<A> fmt::Debug for LocalContext<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalContext")
+            .field("actor_id", &self.actor_id)
+            .finish()
+    }
+}
+
+impl<A> LocalContext<A> {
+    /// Returns the address of the actor.
+    pub fn address(&self) -> LocalAddr<A> {
+        LocalAddr {
+            actor_id: self.actor_id,
+            tx: self.tx.upgrade().unwrap(),
+            rx_exit: self.rx_exit.clone(),
+        }
+    }
+
+    /// Returns the id of the actor.
+    pub fn actor_id(&self) -> ActorId {
+        self.actor_id
+    }
+
+    /// Stop the actor.
+    pub fn stop(&self, err: Option<Error>) {
+        if let Some(tx) = self.tx.upgrade() {
+            tx.send_event(LocalEvent::Stop(err)).ok();
+        }
+    }
+}
+
+/// The address of a `LocalActor`.
+///
+/// Unlike the actor, its address is `Send` and `Sync`.
+/// When all references to `LocalAddr<A>` are dropped, the actor ends.
+pub struct LocalAddr<A> {
+    actor_id: ActorId,
+    tx: Arc<LocalSender<A>>,
+    rx_exit: ExitReceiver,
+}
+
+impl<A> Clone for LocalAddr<A> {
+    fn clone(&self) -> Self {
+        Self {
+            actor_id: self.actor_id,
+            tx: self.tx.clone(),
+            rx_exit: self.rx_exit.clone(),
+        }
+    }
+}
+
+impl<A> PartialEq for LocalAddr<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.actor_id == other.actor_id
+    }
+}
+
+impl<A> Hash for LocalAddr<A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.actor_id.hash(state)
+    }
+}
+
+impl<A> LocalAddr<A> {
+    /// Returns the id of the actor.
+    pub fn actor_id(&self) -> ActorId {
+        self.actor_id
+    }
+
+    /// Returns `false` once the actor has stopped.
+    pub(crate) fn is_alive(&self) -> bool {
+        is_alive(Some(&self.rx_exit))
+    }
+
+    /// Stop the actor.
+    pub fn stop(&mut self, err: Option<Error>) -> Result<()> {
+        self.tx.send_event(LocalEvent::Stop(err))
+    }
+
+    /// Send a message `msg` to the actor and wait for the return value.
+    pub async fn call<T: Message>(&self, msg: T) -> Result<T::Result>
+    where
+        A: LocalHandler<T>,
+    {
+        call_local(&self.tx, msg).await
+    }
+
+    /// Like `call`, but fails with `ActorError::Timeout` if the reply does not arrive within `timeout`.
+    pub async fn call_timeout<T: Message>(&self, msg: T, timeout: Duration) -> Result<T::Result>
+    where
+        A: LocalHandler<T>,
+    {
+        runtime::timeout(timeout, call_local(&self.tx, msg)).await?
+    }
+
+    /// Send a message `msg` to the actor without waiting for the return value.
+    pub fn send<T: Message<Result = ()>>(&self, msg: T) -> Result<()>
+    where
+        A: LocalHandler<T>,
+    {
+        send_local(&self.tx, msg)
+    }
+
+    /// Create a `Caller<T>` for a specific message type
+    pub fn caller<T: Message>(&self) -> Caller<T>
+    where
+        A: LocalHandler<T>,
+    {
+        let weak_tx = Arc::downgrade(&self.tx);
+
+        let closure = move |msg: T| {
+            let weak_tx_option = weak_tx.upgrade();
+            Box::pin(async move {
+                match weak_tx_option {
+                    Some(tx) => call_local(&tx, msg).await,
+                    None => Err(ActorError::Disconnected.into()),
+                }
+            }) as Pin<Box<dyn Future<Output = Result<T::Result>> + Send>>
+        };
+
+        Caller {
+            actor_id: self.actor_id,
+            caller_fn: Box::new(closure),
+        }
+    }
+
+    /// Create a `Sender<T>` for a specific message type
+    pub fn sender<T: Message<Result = ()>>(&self) -> Sender<T>
+    where
+        A: LocalHandler<T>,
+    {
+        let weak_tx = Arc::downgrade(&self.tx);
+
+        let closure = move |msg| match weak_tx.upgrade() {
+            Some(tx) => send_local(&tx, msg),
+            None => Ok(()),
+        };
+
+        Sender {
+            actor_id: self.actor_id,
+            sender_fn: Box::new(closure),
+        }
+    }
+
+    /// Wait for the actor to finish, returning the reason it stopped.
+    pub async fn wait_for_stop(self) -> ExitReason {
+        // The exit channel is only dropped without a value if the actor's task panicked
+        self.rx_exit.await.unwrap_or(ExitReason::Panicked)
+    }
+}
+
+/// Run the handler of `msg` in a child span of `span`, recording how long it took.
+async fn handle_local_message<A, T>(
+    actor: &mut A,
+    ctx: &mut LocalContext<A>,
+    msg: T,
+    span: SenderSpan,
+) -> T::Result
+where
+    A: LocalHandler<T>,
+    T: Message,
+{
+    let actor_id = ctx.actor_id;
+    run_handler::<A, T, _>(actor_id, span, LocalHandler::handle(actor, ctx, msg)).await
+}
+
+fn send_local<A, T>(tx: &LocalSender<A>, msg: T) -> Result<()>
+where
+    A: LocalHandler<T>,
+    T: Message<Result = ()>,
+{
+    let span = SenderSpan::current();
+    tx.send_event(LocalEvent::Exec(Box::new(move |actor, ctx| {
+        Box::pin(async move {
+            handle_local_message(actor, ctx, msg, span).await;
+        })
+    })))
+}
+
+/// Send `msg` through `tx` and wait for the reply, see `reply`.
+async fn call_local<A, T>(tx: &LocalSender<A>, msg: T) -> Result<T::Result>
+where
+    A: LocalHandler<T>,
+    T: Message,
+{
+    let (oneshot_tx, oneshot_rx) = oneshot::channel::<std::result::Result<T::Result, ActorError>>();
+    let span = SenderSpan::current();
+    tx.send_event(LocalEvent::Exec(Box::new(move |actor, ctx| {
+        Box::pin(reply(
+            handle_local_message(actor, ctx, msg, span),
+            oneshot_tx,
+        ))
+    })))?;
+    Ok(oneshot_rx.await.map_err(|_| ActorError::Disconnected)??)
+}
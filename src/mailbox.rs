@@ -0,0 +1,229 @@
+use crate::addr::{ActorEvent, ExecFn};
+use crate::metrics::MailboxGauge;
+use crate::{ActorBuilder, ActorError, ActorId, ActorStatus, Result};
+use futures::channel::mpsc;
+use futures::task::{Context, Poll};
+use futures::Stream;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// Counters shared by both halves of a mailbox, telling whether the actor has work to do.
+///
+/// They are only read by introspection and by the testing helpers, so the orderings are as weak as possible:
+/// a message leaves the queue with `Release`, so that a reader seeing it gone also sees the actor busy.
+#[derive(Debug)]
+pub(crate) struct MailboxStats {
+    queued: AtomicUsize,
+    /// An `ActorStatus`, which is only `Idle` while the actor waits for its next event.
+    status: AtomicU8,
+    gauge: MailboxGauge,
+}
+
+impl MailboxStats {
+    pub(crate) fn new<A>(actor_id: ActorId) -> Self {
+        Self {
+            queued: AtomicUsize::new(0),
+            status: AtomicU8::new(ActorStatus::Idle as u8),
+            gauge: MailboxGauge::new::<A>(actor_id),
+        }
+    }
+
+    pub(crate) fn status(&self) -> ActorStatus {
+        match self.status.load(Ordering::Relaxed) {
+            s if s == ActorStatus::Handling as u8 => ActorStatus::Handling,
+            s if s == ActorStatus::Restarting as u8 => ActorStatus::Restarting,
+            s if s == ActorStatus::Stopping as u8 => ActorStatus::Stopping,
+            _ => ActorStatus::Idle,
+        }
+    }
+
+    fn set_status(&self, status: ActorStatus) {
+        self.status.store(status as u8, Ordering::Relaxed);
+    }
+
+    /// Returns the number of messages waiting in the mailbox.
+    pub(crate) fn len(&self) -> usize {
+        self.queued.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` while the actor is handling a message, being restarted or stopping.
+    pub(crate) fn is_busy(&self) -> bool {
+        self.status() != ActorStatus::Idle
+    }
+
+    /// Returns `true` if the actor has no message waiting and is not busy.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.len() == 0 && !self.is_busy()
+    }
+
+    /// Returns `true` if the actor has messages waiting but has not picked them up yet.
+    pub(crate) fn is_ready(&self) -> bool {
+        self.len() > 0 && !self.is_busy()
+    }
+
+    pub(crate) fn push(&self) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        self.gauge.increment();
+    }
+
+    /// Forget a message that will never be handled.
+    pub(crate) fn cancel(&self) {
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        self.gauge.decrement();
+    }
+
+    /// Take a message out of the mailbox to handle it.
+    pub(crate) fn start_handling(&self) {
+        // Set before the message leaves the queue, so that the actor is never seen idle in between
+        self.set_status(ActorStatus::Handling);
+        self.queued.fetch_sub(1, Ordering::Release);
+        self.gauge.decrement();
+    }
+
+    pub(crate) fn finish_handling(&self) {
+        self.set_status(ActorStatus::Idle);
+    }
+
+    /// Mark the actor busy while its event loop has exited and it is being stopped.
+    pub(crate) fn set_stopping(&self) {
+        self.set_status(ActorStatus::Stopping);
+    }
+}
+
+/// The sending half of an actor mailbox.
+///
+/// Control events (stop, stream removal) always bypass the capacity limit, only messages count against it.
+pub(crate) struct Mailbox<A> {
+    tx: mpsc::UnboundedSender<ActorEvent<A>>,
+    capacity: Option<Arc<Semaphore>>,
+    pub(crate) call_timeout: Option<Duration>,
+    pub(crate) name: Option<Arc<str>>,
+    pub(crate) stats: Arc<MailboxStats>,
+}
+
+/// The receiving half of an actor mailbox.
+pub(crate) struct MailboxReceiver<A> {
+    rx: mpsc::UnboundedReceiver<ActorEvent<A>>,
+    capacity: Option<Arc<Semaphore>>,
+    stats: Arc<MailboxStats>,
+}
+
+pub(crate) fn mailbox<A>(
+    builder: &ActorBuilder,
+    actor_id: ActorId,
+) -> (Mailbox<A>, MailboxReceiver<A>) {
+    let (tx, rx) = mpsc::unbounded();
+    let capacity = builder
+        .mailbox_capacity
+        .map(|capacity| Arc::new(Semaphore::new(capacity)));
+    let stats = Arc::new(MailboxStats::new::<A>(actor_id));
+    (
+        Mailbox {
+            tx,
+            capacity: capacity.clone(),
+            call_timeout: builder.call_timeout,
+            name: builder.name.clone(),
+            stats: stats.clone(),
+        },
+        MailboxReceiver {
+            rx,
+            capacity,
+            stats,
+        },
+    )
+}
+
+impl<A> Mailbox<A> {
+    /// Push a control event or a notification, ignoring the capacity limit.
+    pub(crate) fn send_event(&self, event: ActorEvent<A>) -> Result<()> {
+        let is_message = matches!(event, ActorEvent::Exec(_) | ActorEvent::Notify(_));
+        if is_message {
+            self.stats.push();
+        }
+        if self.tx.unbounded_send(event).is_err() {
+            if is_message {
+                self.stats.cancel();
+            }
+            return Err(ActorError::Disconnected.into());
+        }
+        Ok(())
+    }
+
+    /// Push a message, failing with `ActorError::MailboxFull` if there is no free slot.
+    pub(crate) fn try_send(&self, f: ExecFn<A>) -> Result<()> {
+        if let Some(capacity) = &self.capacity {
+            match capacity.try_acquire() {
+                Ok(permit) => permit.forget(),
+                // The semaphore is only closed once the actor has stopped, so fall through and report a disconnected channel.
+                Err(tokio::sync::TryAcquireError::Closed) => {}
+                Err(tokio::sync::TryAcquireError::NoPermits) => {
+                    return Err(ActorError::MailboxFull.into())
+                }
+            }
+        }
+        self.send_event(ActorEvent::Exec(f))
+    }
+
+    /// Push a message, waiting until there is a free slot.
+    pub(crate) async fn send(&self, f: ExecFn<A>) -> Result<()> {
+        if let Some(capacity) = &self.capacity {
+            if let Ok(permit) = capacity.acquire().await {
+                permit.forget();
+            }
+        }
+        self.send_event(ActorEvent::Exec(f))
+    }
+}
+
+impl<A> MailboxReceiver<A> {
+    /// Mark the actor busy until its event loop runs again, while its supervisor restarts it.
+    pub(crate) fn set_restarting(&self) {
+        self.stats.set_status(ActorStatus::Restarting);
+    }
+
+    /// Mark the actor busy until it has been stopped.
+    pub(crate) fn set_stopping(&self) {
+        self.stats.set_stopping();
+    }
+}
+
+impl<A> Stream for MailboxReceiver<A> {
+    type Item = ActorEvent<A>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // The event loop only asks for the next event once it is done with the previous one
+        self.stats.finish_handling();
+        let res = Pin::new(&mut self.rx).poll_next(cx);
+        match &res {
+            Poll::Ready(Some(ActorEvent::Exec(_))) => {
+                self.stats.start_handling();
+                if let Some(capacity) = &self.capacity {
+                    capacity.add_permits(1);
+                }
+            }
+            Poll::Ready(Some(ActorEvent::Notify(_))) => self.stats.start_handling(),
+            _ => {}
+        }
+        res
+    }
+}
+
+impl<A> Drop for MailboxReceiver<A> {
+    fn drop(&mut self) {
+        self.rx.close();
+        while let Ok(event) = self.rx.try_recv() {
+            if let ActorEvent::Exec(_) | ActorEvent::Notify(_) = event {
+                self.stats.cancel();
+            }
+        }
+        self.stats.finish_handling();
+
+        // Wake up any sender still waiting for capacity
+        if let Some(capacity) = &self.capacity {
+            capacity.close();
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Metrics recorded through the `metrics` facade, when the `metrics` feature is enabled.
+//!
+//! Without the feature, every function here does nothing.
+
+#![cfg_attr(
+    not(feature = "metrics"),
+    allow(unused_variables, clippy::extra_unused_type_parameters)
+)]
+
+use crate::ActorId;
+#[cfg(feature = "metrics")]
+use std::any::type_name;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+/// Gauge of the number of messages waiting in the mailbox of an actor.
+pub(crate) struct MailboxGauge {
+    #[cfg(feature = "metrics")]
+    gauge: metrics::Gauge,
+}
+
+impl MailboxGauge {
+    pub(crate) fn new<A>(actor_id: ActorId) -> Self {
+        Self {
+            #[cfg(feature = "metrics")]
+            gauge: metrics::gauge!(
+                "xactor_mailbox_length",
+                "actor_type" => type_name::<A>(),
+                "actor_id" => actor_id.to_string(),
+            ),
+        }
+    }
+
+    pub(crate) fn increment(&self) {
+        #[cfg(feature = "metrics")]
+        self.gauge.increment(1.0);
+    }
+
+    pub(crate) fn decrement(&self) {
+        #[cfg(feature = "metrics")]
+        self.gauge.decrement(1.0);
+    }
+}
+
+impl std::fmt::Debug for MailboxGauge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MailboxGauge").finish()
+    }
+}
+
+/// Measures how long a handler takes.
+pub(crate) struct HandlerTimer {
+    #[cfg(feature = "metrics")]
+    start: Instant,
+}
+
+impl HandlerTimer {
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(feature = "metrics")]
+            start: Instant::now(),
+        }
+    }
+
+    /// Count a message of type `T` handled by the actor `A`, and record how long it took.
+    pub(crate) fn finish<A, T>(self, actor_id: ActorId) {
+        #[cfg(feature = "metrics")]
+        {
+            let labels = [
+                ("actor_type", type_name::<A>().to_string()),
+                ("actor_id", actor_id.to_string()),
+                ("message_type", type_name::<T>().to_string()),
+            ];
+            metrics::counter!("xactor_messages_processed_total", &labels).increment(1);
+            metrics::histogram!("xactor_handler_duration_seconds", &labels)
+                .record(self.start.elapsed());
+        }
+    }
+}
+
+/// Count a restart of the actor `A` by its supervisor.
+pub(crate) fn record_restart<A>(actor_id: ActorId) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(
+        "xactor_supervisor_restarts_total",
+        "actor_type" => type_name::<A>(),
+        "actor_id" => actor_id.to_string(),
+    )
+    .increment(1);
+}
+
+/// Record the number of subscribers of the broker for messages of type `T`.
+pub(crate) fn set_broker_subscribers<T>(count: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!("xactor_broker_subscribers", "message_type" => type_name::<T>())
+        .set(count as f64);
+}
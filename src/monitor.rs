@@ -0,0 +1,16 @@
+use crate::{ActorId, ExitReason, Message};
+
+/// The message delivered to a watcher when an actor it monitors has stopped.
+///
+/// See `Context::monitor`.
+#[derive(Debug, Clone)]
+pub struct Terminated {
+    /// The id of the actor that stopped.
+    pub actor_id: ActorId,
+    /// The reason the actor stopped.
+    pub reason: ExitReason,
+}
+
+impl Message for Terminated {
+    type Result = ();
+}
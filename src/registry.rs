@@ -0,0 +1,212 @@
+use crate::addr::{is_alive, ExitReceiver};
+use crate::error::Result;
+use crate::runtime::spawn;
+use crate::{Actor, ActorError, ActorId, Addr, Caller, Handler, Message, Sender, WeakAddr};
+use once_cell::sync::OnceCell;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// An actor registered under a name, with the types it can be looked up as.
+struct NamedActor {
+    actor_id: ActorId,
+    rx_exit: Option<ExitReceiver>,
+    entries: HashMap<TypeId, Box<dyn Any + Send>>,
+}
+
+impl NamedActor {
+    /// Returns `false` once the actor has stopped, even if its name has not been removed yet.
+    fn is_alive(&self) -> bool {
+        is_alive(self.rx_exit.as_ref())
+    }
+}
+
+static NAMES: OnceCell<Mutex<HashMap<String, NamedActor>>> = OnceCell::new();
+
+fn names() -> &'static Mutex<HashMap<String, NamedActor>> {
+    NAMES.get_or_init(Default::default)
+}
+
+/// Remove every registered name.
+pub(crate) fn clear() {
+    if let Some(names) = NAMES.get() {
+        names.lock().unwrap().clear();
+    }
+}
+
+/// Record `entry` under `name`, for the actor `actor_id`.
+fn insert<E: Any + Send>(
+    name: &str,
+    actor_id: ActorId,
+    rx_exit: Option<ExitReceiver>,
+    entry: E,
+) -> Result<()> {
+    let mut names = names().lock().unwrap();
+    let named = names.entry(name.to_string()).or_insert_with(|| NamedActor {
+        actor_id,
+        rx_exit: rx_exit.clone(),
+        entries: HashMap::new(),
+    });
+    if named.actor_id != actor_id {
+        if named.is_alive() {
+            return Err(ActorError::NameTaken(name.to_string()).into());
+        }
+        *named = NamedActor {
+            actor_id,
+            rx_exit,
+            entries: HashMap::new(),
+        };
+    }
+    named.entries.insert(TypeId::of::<E>(), Box::new(entry));
+    Ok(())
+}
+
+/// Returns the entry of type `E` registered under `name`.
+fn get<E: Any + Clone>(name: &str) -> Result<E> {
+    names()
+        .lock()
+        .unwrap()
+        .get(name)
+        .filter(|named| named.is_alive())
+        .and_then(|named| named.entries.get(&TypeId::of::<E>()))
+        .and_then(|entry| entry.downcast_ref::<E>())
+        .cloned()
+        .ok_or_else(|| ActorError::NameNotFound(name.to_string()).into())
+}
+
+/// A global registry of actors by name.
+///
+/// An actor registered with `Registry::register` can be looked up by name as an `Addr<A>`, and as a `Caller<T>` or a
+/// `Sender<T>` for the message types added to its `Registration`, without knowing the type of the actor.
+/// The registry only keeps weak references, and the name is removed once the actor stops.
+///
+/// # Examples
+///
+/// ```rust
+/// use xactor::*;
+///
+/// #[message(result = "i32")]
+/// struct Add(i32);
+///
+/// #[derive(Default)]
+/// struct Counter(i32);
+///
+/// impl Actor for Counter {}
+///
+/// impl Handler<Add> for Counter {
+///     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: Add) -> i32 {
+///         self.0 += msg.0;
+///         self.0
+///     }
+/// }
+///
+/// #[xactor::main]
+/// async fn main() -> Result<()> {
+///     let mut addr = Counter::default().start().await?;
+///     Registry::register("counter", &addr)?.caller::<Add>()?;
+///
+///     let counter = Registry::caller::<Add>("counter")?;
+///     assert_eq!(counter.call(Add(2)).await?, 2);
+///     assert_eq!(Registry::lookup::<Counter>("counter")?.call(Add(3)).await?, 5);
+///
+///     // The name is removed once the actor stops
+///     addr.stop(None)?;
+///     addr.wait_for_stop().await;
+///     assert!(Registry::caller::<Add>("counter").is_err());
+///     Ok(())
+/// }
+/// ```
+pub struct Registry;
+
+impl Registry {
+    /// Register the actor at `addr` under `name`, so that it can be looked up as an `Addr<A>`.
+    ///
+    /// Fails with `ActorError::NameTaken` if another actor is registered under `name`.
+    pub fn register<A: Actor>(name: impl Into<String>, addr: &Addr<A>) -> Result<Registration<A>> {
+        let name = name.into();
+        let actor_id = addr.actor_id;
+        insert(&name, actor_id, addr.rx_exit.clone(), addr.downgrade())?;
+
+        if let Some(rx_exit) = addr.rx_exit.clone() {
+            let name = name.clone();
+            spawn(async move {
+                rx_exit.await.ok();
+                let mut names = names().lock().unwrap();
+                if names.get(&name).map(|named| named.actor_id) == Some(actor_id) {
+                    names.remove(&name);
+                }
+            });
+        }
+
+        Ok(Registration {
+            name,
+            addr: addr.downgrade(),
+        })
+    }
+
+    /// Remove `name` from the registry, whatever the types it was registered as.
+    pub fn unregister(name: &str) {
+        names().lock().unwrap().remove(name);
+    }
+
+    /// Returns the address of the actor of type `A` registered under `name`.
+    ///
+    /// Fails with `ActorError::NameNotFound` if no such actor is registered.
+    pub fn lookup<A: Actor>(name: &str) -> Result<Addr<A>> {
+        get::<WeakAddr<A>>(name)?
+            .upgrade()
+            .ok_or_else(|| ActorError::NameNotFound(name.to_string()).into())
+    }
+
+    /// Returns a `Caller<T>` for the actor registered under `name`.
+    ///
+    /// Fails with `ActorError::NameNotFound` unless `T` was added with `Registration::caller`.
+    pub fn caller<T: Message>(name: &str) -> Result<Caller<T>> {
+        get(name)
+    }
+
+    /// Returns a `Sender<T>` for the actor registered under `name`.
+    ///
+    /// Fails with `ActorError::NameNotFound` unless `T` was added with `Registration::sender`.
+    pub fn sender<T: Message<Result = ()>>(name: &str) -> Result<Sender<T>> {
+        get(name)
+    }
+}
+
+/// An actor registered with `Registry::register`, to which more message types can be added.
+pub struct Registration<A> {
+    name: String,
+    addr: WeakAddr<A>,
+}
+
+impl<A: Actor> Registration<A> {
+    /// Allow looking the actor up with `Registry::caller::<T>`.
+    pub fn caller<T: Message>(self) -> Result<Self>
+    where
+        A: Handler<T>,
+    {
+        let addr = self.addr.upgrade().ok_or(ActorError::Disconnected)?;
+        insert(
+            &self.name,
+            addr.actor_id,
+            addr.rx_exit.clone(),
+            addr.caller::<T>(),
+        )?;
+        Ok(self)
+    }
+
+    /// Allow looking the actor up with `Registry::sender::<T>`.
+    pub fn sender<T: Message<Result = ()>>(self) -> Result<Self>
+    where
+        A: Handler<T>,
+    {
+        let addr = self.addr.upgrade().ok_or(ActorError::Disconnected)?;
+        insert(
+            &self.name,
+            addr.actor_id,
+            addr.rx_exit.clone(),
+            addr.sender::<T>(),
+        )?;
+        Ok(self)
+    }
+}
@@ -1,10 +1,297 @@
+use crate::testing;
+use crate::ActorError;
 use futures::Future;
-pub use tokio::{task::spawn, time::sleep, time::timeout};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
+#[cfg(not(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "runtime-smol"
+)))]
+compile_error!(
+    "one of the features `xactor/runtime-tokio`, `xactor/runtime-async-std` or `xactor/runtime-smol` must be enabled"
+);
+
+#[cfg(any(
+    all(feature = "runtime-tokio", feature = "runtime-async-std"),
+    all(feature = "runtime-tokio", feature = "runtime-smol"),
+    all(feature = "runtime-async-std", feature = "runtime-smol"),
+))]
+compile_error!(
+    r#"
+    features `xactor/runtime-tokio`, `xactor/runtime-async-std` and `xactor/runtime-smol` are mutually exclusive.
+    If you are trying to disable tokio set `default-features = false`.
+"#
+);
+
+/// A handle to a task started with `spawn`, resolving to its output.
+///
+/// Dropping the handle detaches the task, it keeps running in the background.
+pub struct JoinHandle<T> {
+    #[cfg(feature = "runtime-tokio")]
+    inner: tokio::task::JoinHandle<T>,
+    #[cfg(feature = "runtime-async-std")]
+    inner: async_std::task::JoinHandle<T>,
+    #[cfg(feature = "runtime-smol")]
+    inner: Option<smol::Task<T>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    #[cfg(feature = "runtime-tokio")]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        Pin::new(&mut self.inner).poll(cx).map(|res| match res {
+            Ok(output) => output,
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        })
+    }
+
+    #[cfg(feature = "runtime-async-std")]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        Pin::new(&mut self.inner).poll(cx)
+    }
+
+    #[cfg(feature = "runtime-smol")]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        Pin::new(self.inner.as_mut().unwrap()).poll(cx)
+    }
+}
+
+#[cfg(feature = "runtime-smol")]
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        if let Some(task) = self.inner.take() {
+            task.detach();
+        }
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+static HANDLE: once_cell::sync::OnceCell<tokio::runtime::Handle> = once_cell::sync::OnceCell::new();
+
+/// Run xactor on an existing tokio runtime.
+///
+/// By default, actors are spawned on the tokio runtime of the calling task, and `block_on` creates a new one.
+/// Once a handle is set, actors are always spawned on its runtime, including from threads that are not
+/// running inside a tokio runtime.
+///
+/// The handle can only be set once, this returns it back if one was already set.
+///
+/// # Examples
+///
+/// ```rust
+/// use xactor::*;
+///
+/// struct MyActor;
+///
+/// impl Actor for MyActor {}
+///
+/// fn main() -> Result<()> {
+///     let rt = tokio::runtime::Runtime::new()?;
+///     set_runtime_handle(rt.handle().clone()).ok();
+///
+///     // Actors can now be started from any thread
+///     let addr = futures::executor::block_on(MyActor.start())?;
+///     assert!(System::running_actors().contains(&addr.actor_id()));
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "runtime-tokio")]
+pub fn set_runtime_handle(handle: tokio::runtime::Handle) -> Result<(), tokio::runtime::Handle> {
+    HANDLE.set(handle)
+}
+
+/// Enter the runtime set with `set_runtime_handle`, if the current thread is not already inside a runtime.
+#[cfg(feature = "runtime-tokio")]
+fn enter() -> Option<tokio::runtime::EnterGuard<'static>> {
+    match (HANDLE.get(), tokio::runtime::Handle::try_current()) {
+        (Some(handle), Err(_)) => Some(handle.enter()),
+        _ => None,
+    }
+}
+
+/// Spawn a task on the runtime selected by the `runtime-*` features.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(feature = "runtime-tokio")]
+    let inner = match HANDLE.get() {
+        Some(handle) => handle.spawn(future),
+        None => tokio::task::spawn(future),
+    };
+    #[cfg(feature = "runtime-async-std")]
+    let inner = async_std::task::spawn(future);
+    #[cfg(feature = "runtime-smol")]
+    let inner = Some(smol::spawn(future));
+
+    JoinHandle { inner }
+}
+
+#[cfg(feature = "runtime-smol")]
+thread_local! {
+    static LOCAL_EXECUTOR: smol::LocalExecutor<'static> = const { smol::LocalExecutor::new() };
+}
+
+/// Spawn a task that does not need to be `Send` on the current thread.
+///
+/// The task runs on the thread that spawned it, which must be running `block_on`
+/// (with tokio, any `tokio::task::LocalSet` will do).
+///
+/// # Panics
+///
+/// With tokio, panics if called outside of a `LocalSet`.
+pub fn spawn_local<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    #[cfg(feature = "runtime-tokio")]
+    let inner = tokio::task::spawn_local(future);
+    #[cfg(feature = "runtime-async-std")]
+    let inner = async_std::task::spawn_local(future);
+    #[cfg(feature = "runtime-smol")]
+    let inner = Some(LOCAL_EXECUTOR.with(|executor| executor.spawn(future)));
+
+    JoinHandle { inner }
+}
+
+/// Wait until `duration` has elapsed.
+///
+/// While the clock is paused with `testing::pause`, this waits for `testing::advance` instead,
+/// counting from the call to `sleep` rather than from the first poll.
+pub fn sleep(duration: Duration) -> impl Future<Output = ()> + Send + 'static {
+    let virtual_sleep = testing::virtual_sleep(duration);
+    async move {
+        match virtual_sleep {
+            Some(sleep) => sleep.await,
+            None => real_sleep(duration).await,
+        }
+    }
+}
+
+async fn real_sleep(duration: Duration) {
+    #[cfg(feature = "runtime-tokio")]
+    {
+        let sleep = {
+            let _guard = enter();
+            tokio::time::sleep(duration)
+        };
+        sleep.await;
+    }
+    #[cfg(feature = "runtime-async-std")]
+    async_std::task::sleep(duration).await;
+    #[cfg(feature = "runtime-smol")]
+    smol::Timer::after(duration).await;
+}
+
+/// Wait for `future` to complete, failing with `ActorError::Timeout` if it takes longer than `duration`.
+///
+/// While the clock is paused with `testing::pause`, the timeout only elapses with `testing::advance`.
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, ActorError> {
+    if let Some(sleep) = testing::virtual_sleep(duration) {
+        return match futures::future::select(Box::pin(future), sleep).await {
+            futures::future::Either::Left((output, _)) => Ok(output),
+            futures::future::Either::Right(_) => Err(ActorError::Timeout),
+        };
+    }
+    #[cfg(feature = "runtime-tokio")]
+    return {
+        let timeout = {
+            let _guard = enter();
+            tokio::time::timeout(duration, future)
+        };
+        timeout.await.map_err(|_| ActorError::Timeout)
+    };
+    #[cfg(feature = "runtime-async-std")]
+    return async_std::future::timeout(duration, future)
+        .await
+        .map_err(|_| ActorError::Timeout);
+    #[cfg(feature = "runtime-smol")]
+    return match futures::future::select(Box::pin(future), smol::Timer::after(duration)).await {
+        futures::future::Either::Left((output, _)) => Ok(output),
+        futures::future::Either::Right(_) => Err(ActorError::Timeout),
+    };
+}
+
+/// Returns the current time, as seen by `sleep` and `timeout`.
+pub(crate) fn now() -> Instant {
+    testing::virtual_now().unwrap_or_else(Instant::now)
+}
+
+/// Yield to the other tasks of the runtime.
+pub(crate) async fn yield_now() {
+    #[cfg(feature = "runtime-tokio")]
+    tokio::task::yield_now().await;
+    #[cfg(feature = "runtime-async-std")]
+    async_std::task::yield_now().await;
+    #[cfg(feature = "runtime-smol")]
+    smol::future::yield_now().await;
+}
+
+/// Options for the runtime created by `block_on_with`.
+///
+/// They only apply to tokio, and are ignored by the other runtimes.
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeOptions {
+    current_thread: bool,
+    worker_threads: Option<usize>,
+}
+
+impl RuntimeOptions {
+    /// Create the default options, a multi-threaded runtime with one worker thread per core.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run all tasks on the current thread.
+    pub fn current_thread(mut self) -> Self {
+        self.current_thread = true;
+        self
+    }
+
+    /// Set the number of worker threads of a multi-threaded runtime.
+    pub fn worker_threads(mut self, worker_threads: usize) -> Self {
+        self.worker_threads = Some(worker_threads);
+        self
+    }
+}
+
+/// Run `future` to completion on the runtime selected by the `runtime-*` features, blocking the current thread.
+///
+/// Tasks started with `spawn_local` from the current thread run while this is blocking.
 pub fn block_on<F, T>(future: F) -> T
 where
     F: Future<Output = T>,
 {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(future)
+    block_on_with(RuntimeOptions::default(), future)
+}
+
+/// Like `block_on`, creating the runtime with `options`.
+#[allow(unused_variables)]
+pub fn block_on_with<F, T>(options: RuntimeOptions, future: F) -> T
+where
+    F: Future<Output = T>,
+{
+    #[cfg(feature = "runtime-tokio")]
+    return {
+        let mut builder = if options.current_thread {
+            tokio::runtime::Builder::new_current_thread()
+        } else {
+            tokio::runtime::Builder::new_multi_thread()
+        };
+        if let Some(worker_threads) = options.worker_threads {
+            builder.worker_threads(worker_threads);
+        }
+        let runtime = builder.enable_all().build().unwrap();
+        tokio::task::LocalSet::new().block_on(&runtime, future)
+    };
+    #[cfg(feature = "runtime-async-std")]
+    return async_std::task::block_on(future);
+    #[cfg(feature = "runtime-smol")]
+    return LOCAL_EXECUTOR.with(|executor| smol::block_on(executor.run(future)));
 }
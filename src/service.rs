@@ -1,15 +1,15 @@
 use crate::actor::ActorManager;
 use crate::error::Result;
-use crate::{Actor, Addr};
-use anyhow::anyhow;
+use crate::{Actor, ActorError, Addr, LocalActor, LocalAddr};
 use fnv::FnvHasher;
 use futures::lock::Mutex;
 use futures::Future;
 use once_cell::sync::OnceCell;
-use std::any::{Any, TypeId};
+use std::any::{type_name, Any, TypeId};
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::hash::BuildHasherDefault;
+use std::hash::{BuildHasherDefault, Hash};
+use std::pin::Pin;
 
 static REGISTRY: OnceCell<
     Mutex<HashMap<TypeId, Box<dyn Any + Send>, BuildHasherDefault<FnvHasher>>>,
@@ -17,8 +17,10 @@ static REGISTRY: OnceCell<
 
 /// Trait define a global service.
 ///
-/// The service is a global actor.
-/// You can use `Actor::from_registry` to get the address `Addr<A>` of the service.
+/// The service is a global actor, with at most one instance running.
+/// You can use `Service::from_registry` to get the address `Addr<A>` of the service,
+/// which starts it with `Default` the first time, and `Service::stop_service` to stop it.
+/// A service without `Default` is started with `Service::start_service`, and looked up with `Service::lookup`.
 ///
 /// # Examples
 ///
@@ -35,7 +37,6 @@ static REGISTRY: OnceCell<
 ///
 /// impl Service for MyService {}
 ///
-/// #[async_trait::async_trait]
 /// impl Handler<AddMsg> for MyService {
 ///     async fn handle(&mut self, ctx: &mut Context<Self>, msg: AddMsg) -> i32 {
 ///         self.0 += msg.0;
@@ -48,56 +49,462 @@ static REGISTRY: OnceCell<
 ///     let mut addr = MyService::from_registry().await?;
 ///     assert_eq!(addr.call(AddMsg(1)).await?, 1);
 ///     assert_eq!(addr.call(AddMsg(5)).await?, 6);
+///
+///     // A new instance is started after the service is stopped
+///     MyService::stop_service().await?;
+///     let addr = MyService::from_registry().await?;
+///     assert_eq!(addr.call(AddMsg(1)).await?, 1);
 ///     Ok(())
 /// }
 /// ```
 pub trait Service: Actor {
+    /// The services this one depends on, started before it.
+    ///
+    /// See `Dependency`.
+    fn dependencies() -> Vec<Dependency> {
+        Vec::new()
+    }
+
+    /// Start this actor as the service, after its dependencies.
+    ///
+    /// Fails with `ActorError::ServiceAlreadyRunning` if the service is already running.
     fn start_service(self) -> impl Future<Output = Result<Addr<Self>>> + Send {
         async move {
-            let registry = REGISTRY.get_or_init(Default::default);
-            let mut registry = registry.lock().await;
-            let actor_manager = ActorManager::new();
-            registry.insert(TypeId::of::<Self>(), Box::new(actor_manager.address()));
-            drop(registry);
+            start_dependencies::<Self>().await?;
+            let actor_manager = {
+                let registry = REGISTRY.get_or_init(Default::default);
+                let mut registry = registry.lock().await;
+                if service_addr::<Self>(&registry).is_some() {
+                    return Err(ActorError::ServiceAlreadyRunning.into());
+                }
+                let actor_manager = ActorManager::new();
+                registry.insert(TypeId::of::<Self>(), Box::new(actor_manager.address()));
+                actor_manager
+            };
 
             actor_manager.start_actor(self).await
         }
     }
 
-    fn from_registry() -> impl Future<Output = Result<Addr<Self>>> + Send {
+    /// Returns the address of the service, without starting it.
+    ///
+    /// Fails with `ActorError::ServiceNotFound` if the service is not running.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use xactor::*;
+    ///
+    /// #[message(result = "String")]
+    /// struct GetUrl;
+    ///
+    /// struct Config {
+    ///     url: String,
+    /// }
+    ///
+    /// impl Actor for Config {}
+    ///
+    /// impl Service for Config {}
+    ///
+    /// impl Handler<GetUrl> for Config {
+    ///     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: GetUrl) -> String {
+    ///         self.url.clone()
+    ///     }
+    /// }
+    ///
+    /// #[xactor::main]
+    /// async fn main() -> Result<()> {
+    ///     assert!(Config::lookup().await.is_err());
+    ///
+    ///     let url = "postgres://localhost".to_string();
+    ///     Config { url }.start_service().await?;
+    ///     assert_eq!(Config::lookup().await?.call(GetUrl).await?, "postgres://localhost");
+    ///     Ok(())
+    /// }
+    /// ```
+    fn lookup() -> impl Future<Output = Result<Addr<Self>>> + Send {
         async move {
-            let registry = REGISTRY.get().ok_or(anyhow!("registry not initialized"))?;
-            let mut registry = registry.lock().await;
+            let addr = match REGISTRY.get() {
+                Some(registry) => service_addr::<Self>(&*registry.lock().await),
+                None => None,
+            };
+            addr.ok_or_else(|| ActorError::ServiceNotFound.into())
+        }
+    }
 
-            match registry.get_mut(&TypeId::of::<Self>()) {
-                Some(addr) => Ok(addr.downcast_ref::<Addr<Self>>().unwrap().clone()),
-                None => Err(anyhow!("service not found")),
+    /// Returns the address of the service, starting it with `Default`, after its dependencies, if it is not running.
+    ///
+    /// Concurrent calls all get the same instance.
+    fn from_registry() -> impl Future<Output = Result<Addr<Self>>> + Send
+    where
+        Self: Default,
+    {
+        async move {
+            let registry = REGISTRY.get_or_init(Default::default);
+            if let Some(addr) = service_addr::<Self>(&*registry.lock().await) {
+                return Ok(addr);
             }
+            start_dependencies::<Self>().await?;
+            start_default::<Self>().await
+        }
+    }
+
+    /// Remove the service from the registry, stop it and wait for it to stop.
+    ///
+    /// Fails with `ActorError::ServiceNotFound` if the service is not running.
+    fn stop_service() -> impl Future<Output = Result<()>> + Send {
+        async move {
+            let addr = match REGISTRY.get() {
+                Some(registry) => registry
+                    .lock()
+                    .await
+                    .remove(&TypeId::of::<Self>())
+                    .map(|addr| *addr.downcast::<Addr<Self>>().unwrap()),
+                None => None,
+            };
+            let mut addr = addr
+                .filter(Addr::is_alive)
+                .ok_or(ActorError::ServiceNotFound)?;
+            addr.stop(None).ok();
+            addr.wait_for_stop().await;
+            Ok(())
+        }
+    }
+}
+
+/// Start the service `S` with `Default` if it is not running, without starting its dependencies.
+async fn start_default<S: Service + Default>() -> Result<Addr<S>> {
+    let actor_manager = {
+        let registry = REGISTRY.get_or_init(Default::default);
+        let mut registry = registry.lock().await;
+        if let Some(addr) = service_addr::<S>(&registry) {
+            return Ok(addr);
+        }
+        // Registered before starting, so that concurrent lookups get the same instance
+        let actor_manager = ActorManager::new();
+        registry.insert(TypeId::of::<S>(), Box::new(actor_manager.address()));
+        actor_manager
+    };
+
+    actor_manager.start_actor(S::default()).await
+}
+
+/// A service that another service depends on, returned by `Service::dependencies`.
+///
+/// Before a service is started, its dependencies, and theirs, are started with `Default` in topological order.
+/// `System::shutdown` stops services in the reverse order they were started, so a service is stopped before its
+/// dependencies. Starting a service that depends on itself, even indirectly, fails with
+/// `ActorError::ServiceDependencyCycle`.
+///
+/// Dependencies can also be declared with `#[service(depends_on(...))]` on `#[derive(Service)]`.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::Mutex;
+/// use xactor::*;
+///
+/// static EVENTS: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+///
+/// #[derive(Default)]
+/// struct DbPool;
+///
+/// impl Actor for DbPool {
+///     async fn started(&mut self, _ctx: &mut Context<Self>) -> Result<()> {
+///         EVENTS.lock().unwrap().push("db pool started");
+///         Ok(())
+///     }
+///
+///     async fn stopped(&mut self, _ctx: &mut Context<Self>) {
+///         EVENTS.lock().unwrap().push("db pool stopped");
+///     }
+/// }
+///
+/// impl Service for DbPool {}
+///
+/// #[derive(Default, Service)]
+/// #[service(depends_on(DbPool))]
+/// struct Cache;
+///
+/// impl Actor for Cache {
+///     async fn started(&mut self, _ctx: &mut Context<Self>) -> Result<()> {
+///         EVENTS.lock().unwrap().push("cache started");
+///         Ok(())
+///     }
+///
+///     async fn stopped(&mut self, _ctx: &mut Context<Self>) {
+///         EVENTS.lock().unwrap().push("cache stopped");
+///     }
+/// }
+///
+/// #[xactor::main]
+/// async fn main() -> Result<()> {
+///     Cache::from_registry().await?;
+///     System::shutdown(System::DEFAULT_SHUTDOWN_TIMEOUT).await?;
+///     assert_eq!(
+///         *EVENTS.lock().unwrap(),
+///         ["db pool started", "cache started", "cache stopped", "db pool stopped"]
+///     );
+///     Ok(())
+/// }
+/// ```
+pub struct Dependency {
+    type_id: TypeId,
+    type_name: &'static str,
+    dependencies: fn() -> Vec<Dependency>,
+    start: fn() -> Pin<Box<dyn Future<Output = Result<()>> + Send>>,
+}
+
+impl Dependency {
+    /// A dependency on the service `S`.
+    pub fn of<S: Service + Default>() -> Self {
+        fn start<S: Service + Default>() -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+            Box::pin(async { start_default::<S>().await.map(|_| ()) })
+        }
+
+        Self {
+            type_id: TypeId::of::<S>(),
+            type_name: type_name::<S>(),
+            dependencies: S::dependencies,
+            start: start::<S>,
+        }
+    }
+}
+
+/// Start the dependencies of the service `S` in topological order.
+async fn start_dependencies<S: Service>() -> Result<()> {
+    let mut order = Vec::new();
+    let mut path = vec![(TypeId::of::<S>(), type_name::<S>())];
+    sort_dependencies(S::dependencies(), &mut path, &mut order)?;
+    for dependency in order {
+        (dependency.start)().await?;
+    }
+    Ok(())
+}
+
+/// Append `dependencies` to `order`, each one after its own dependencies.
+///
+/// `path` holds the services whose dependencies are being sorted, to detect cycles.
+fn sort_dependencies(
+    dependencies: Vec<Dependency>,
+    path: &mut Vec<(TypeId, &'static str)>,
+    order: &mut Vec<Dependency>,
+) -> Result<()> {
+    for dependency in dependencies {
+        if let Some(pos) = path.iter().position(|(id, _)| *id == dependency.type_id) {
+            let mut cycle: Vec<_> = path[pos..].iter().map(|(_, name)| *name).collect();
+            cycle.push(dependency.type_name);
+            return Err(ActorError::ServiceDependencyCycle(cycle).into());
+        }
+        if order
+            .iter()
+            .any(|sorted| sorted.type_id == dependency.type_id)
+        {
+            continue;
+        }
+        path.push((dependency.type_id, dependency.type_name));
+        sort_dependencies((dependency.dependencies)(), path, order)?;
+        path.pop();
+        order.push(dependency);
+    }
+    Ok(())
+}
+
+/// Returns the address of the service `A`, if it is running.
+fn service_addr<A: Actor>(
+    registry: &HashMap<TypeId, Box<dyn Any + Send>, BuildHasherDefault<FnvHasher>>,
+) -> Option<Addr<A>> {
+    registry
+        .get(&TypeId::of::<A>())
+        .map(|addr| addr.downcast_ref::<Addr<A>>().unwrap())
+        .filter(|addr| addr.is_alive())
+        .cloned()
+}
+
+static KEYED_REGISTRY: OnceCell<
+    Mutex<HashMap<TypeId, Box<dyn Any + Send>, BuildHasherDefault<FnvHasher>>>,
+> = OnceCell::new();
+
+/// Trait define a global service with one instance per key.
+///
+/// Each instance is registered under `(TypeId::of::<Self>(), key)`.
+/// `KeyedService::from_registry_keyed` returns the address of the instance for a key,
+/// starting it with `KeyedService::create` if it is not running yet.
+///
+/// # Examples
+///
+/// ```rust
+/// use xactor::*;
+///
+/// #[message(result = "String")]
+/// struct Describe;
+///
+/// struct ConnectionPool {
+///     shard: u32,
+/// }
+///
+/// impl Actor for ConnectionPool {}
+///
+/// impl KeyedService for ConnectionPool {
+///     type Key = u32;
+///
+///     fn create(shard: &u32) -> Self {
+///         ConnectionPool { shard: *shard }
+///     }
+/// }
+///
+/// impl Handler<Describe> for ConnectionPool {
+///     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Describe) -> String {
+///         format!("pool for shard {}", self.shard)
+///     }
+/// }
+///
+/// #[xactor::main]
+/// async fn main() -> Result<()> {
+///     let shard1 = ConnectionPool::from_registry_keyed(1).await?;
+///     let shard2 = ConnectionPool::from_registry_keyed(2).await?;
+///     assert_eq!(shard1.call(Describe).await?, "pool for shard 1");
+///     assert_eq!(shard2.call(Describe).await?, "pool for shard 2");
+///
+///     // The same instance is returned for the same key
+///     assert!(ConnectionPool::from_registry_keyed(1).await? == shard1);
+///     Ok(())
+/// }
+/// ```
+pub trait KeyedService: Actor {
+    /// The key telling the instances apart.
+    type Key: Hash + Eq + Clone + Send + Sync + 'static;
+
+    /// Create the instance for `key`, when it is first looked up.
+    fn create(key: &Self::Key) -> Self;
+
+    /// Start this actor as the instance for `key`.
+    ///
+    /// Fails with `ActorError::ServiceAlreadyRunning` if an instance is already running for `key`.
+    fn start_service_keyed(
+        self,
+        key: Self::Key,
+    ) -> impl Future<Output = Result<Addr<Self>>> + Send {
+        async move {
+            let actor_manager = {
+                let registry = KEYED_REGISTRY.get_or_init(Default::default);
+                let mut registry = registry.lock().await;
+                let instances = keyed_instances::<Self>(&mut registry);
+                if instances.get(&key).is_some_and(Addr::is_alive) {
+                    return Err(ActorError::ServiceAlreadyRunning.into());
+                }
+                let actor_manager = ActorManager::new();
+                instances.insert(key, actor_manager.address());
+                actor_manager
+            };
+
+            actor_manager.start_actor(self).await
+        }
+    }
+
+    /// Returns the address of the instance for `key`, starting it with `create` if it is not running.
+    fn from_registry_keyed(key: Self::Key) -> impl Future<Output = Result<Addr<Self>>> + Send {
+        async move {
+            let actor_manager = {
+                let registry = KEYED_REGISTRY.get_or_init(Default::default);
+                let mut registry = registry.lock().await;
+                let instances = keyed_instances::<Self>(&mut registry);
+                match instances.get(&key) {
+                    Some(addr) if addr.is_alive() => return Ok(addr.clone()),
+                    _ => {}
+                }
+                // Registered before starting, so that concurrent lookups get the same instance
+                let actor_manager = ActorManager::new();
+                instances.insert(key.clone(), actor_manager.address());
+                actor_manager
+            };
+
+            actor_manager.start_actor(Self::create(&key)).await
         }
     }
 }
 
+/// Returns the instances of `A` in the keyed registry.
+fn keyed_instances<A: KeyedService>(
+    registry: &mut HashMap<TypeId, Box<dyn Any + Send>, BuildHasherDefault<FnvHasher>>,
+) -> &mut HashMap<A::Key, Addr<A>> {
+    registry
+        .entry(TypeId::of::<A>())
+        .or_insert_with(|| Box::new(HashMap::<A::Key, Addr<A>>::new()))
+        .downcast_mut()
+        .unwrap()
+}
+
+/// Remove all services from the global registry, and the local registry of the current thread.
+pub(crate) async fn clear_registry() {
+    if let Some(registry) = REGISTRY.get() {
+        registry.lock().await.clear();
+    }
+    if let Some(registry) = KEYED_REGISTRY.get() {
+        registry.lock().await.clear();
+    }
+    LOCAL_REGISTRY.with(|registry| registry.borrow_mut().clear());
+}
+
 thread_local! {
-    static LOCAL_REGISTRY: RefCell<HashMap<TypeId, Box<dyn Any + Send>, BuildHasherDefault<FnvHasher>>> = Default::default();
+    static LOCAL_REGISTRY: RefCell<HashMap<TypeId, Box<dyn Any>, BuildHasherDefault<FnvHasher>>> = Default::default();
 }
 
 /// Trait define a local service.
 ///
-/// The service is a thread local actor.
-/// You can use `Actor::from_registry` to get the address `Addr<A>` of the service.
-pub trait LocalService: Actor {
-    fn start_service(self) -> impl Future<Output = Result<Addr<Self>>> + Send {
+/// The service is a thread local `LocalActor`, so its state does not need to be `Send`.
+/// You can use `LocalService::from_registry` to get the address `LocalAddr<A>` of the service
+/// on the thread that started it, until it stops.
+///
+/// # Migrating from 0.7
+///
+/// `LocalService` used to be implemented by an `Actor` and return an `Addr`. It is now implemented by a
+/// `LocalActor` and returns a `LocalAddr`: implement `LocalActor` and `LocalHandler` instead of `Actor`
+/// and `Handler`, whose methods take a `LocalContext`. An actor whose state is `Send` can implement `Service`
+/// instead, to be shared by all threads.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::rc::Rc;
+/// use xactor::*;
+///
+/// #[message(result = "usize")]
+/// struct Count;
+///
+/// #[derive(Default)]
+/// struct MyService(Rc<()>);
+///
+/// impl LocalActor for MyService {}
+///
+/// impl LocalService for MyService {}
+///
+/// impl LocalHandler<Count> for MyService {
+///     async fn handle(&mut self, _ctx: &mut LocalContext<Self>, _msg: Count) -> usize {
+///         Rc::strong_count(&self.0)
+///     }
+/// }
+///
+/// #[xactor::main]
+/// async fn main() -> Result<()> {
+///     MyService::default().start_service().await?;
+///     let mut addr = MyService::from_registry().await?;
+///     assert_eq!(addr.call(Count).await?, 1);
+///
+///     // The service is removed from the registry once it stops
+///     addr.stop(None)?;
+///     addr.wait_for_stop().await;
+///     assert!(MyService::from_registry().await.is_err());
+///     Ok(())
+/// }
+/// ```
+pub trait LocalService: LocalActor {
+    fn start_service(self) -> impl Future<Output = Result<LocalAddr<Self>>> {
         async move {
-            let res = LOCAL_REGISTRY.with(|registry| {
-                registry
-                    .borrow_mut()
-                    .get_mut(&TypeId::of::<Self>())
-                    .map(|addr| addr.downcast_ref::<Addr<Self>>().unwrap().clone())
-            });
-            match res {
+            match local_service_addr::<Self>() {
                 Some(addr) => Ok(addr),
                 None => {
-                    let addr = ActorManager::new().start_actor(self).await?;
+                    let addr = self.start().await?;
                     LOCAL_REGISTRY.with(|registry| {
                         registry
                             .borrow_mut()
@@ -109,18 +516,19 @@ pub trait LocalService: Actor {
         }
     }
 
-    fn from_registry() -> impl Future<Output = Result<Addr<Self>>> + Send {
-        async move {
-            let res = LOCAL_REGISTRY.with(|registry| {
-                registry
-                    .borrow_mut()
-                    .get_mut(&TypeId::of::<Self>())
-                    .map(|addr| addr.downcast_ref::<Addr<Self>>().unwrap().clone())
-            });
-            match res {
-                Some(addr) => Ok(addr),
-                None => Err(anyhow!("service not found")),
-            }
-        }
+    fn from_registry() -> impl Future<Output = Result<LocalAddr<Self>>> {
+        async move { local_service_addr::<Self>().ok_or_else(|| ActorError::ServiceNotFound.into()) }
     }
 }
+
+/// Returns the address of the local service `A` started on this thread, if it is running.
+fn local_service_addr<A: LocalActor>() -> Option<LocalAddr<A>> {
+    LOCAL_REGISTRY.with(|registry| {
+        registry
+            .borrow()
+            .get(&TypeId::of::<A>())
+            .map(|addr| addr.downcast_ref::<LocalAddr<A>>().unwrap())
+            .filter(|addr| addr.is_alive())
+            .cloned()
+    })
+}
@@ -1,13 +1,27 @@
-use crate::addr::ActorEvent;
+use crate::actor::{run_event_loop, stop_actor};
+use crate::addr::ExitReason;
 use crate::error::Result;
-use crate::runtime::spawn;
-use crate::{Actor, Addr, Context};
-use futures::StreamExt;
+use crate::metrics;
+use crate::runtime::{self, sleep, spawn};
+use crate::system;
+use crate::trace;
+use crate::{Actor, ActorBuilder, Addr, Context};
+use futures::channel::oneshot;
+use futures::FutureExt;
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Actor supervisor
 ///
 /// Supervisor gives the actor the ability to restart after failure.
 /// When the actor fails, recreate a new actor instance and replace it.
+///
+/// How often and how quickly the actor is restarted is controlled by a `SupervisorStrategy`,
+/// set with `ActorBuilder::supervisor_strategy`. Once the supervisor gives up, the actor stops
+/// and `Addr::wait_for_stop` returns the reason of its last failure.
 pub struct Supervisor;
 
 impl Supervisor {
@@ -32,21 +46,18 @@ impl Supervisor {
     ///
     /// impl Actor for MyActor {}
     ///
-    /// #[async_trait::async_trait]
     /// impl Handler<Add> for MyActor {
     ///     async fn handle(&mut self, ctx: &mut Context<Self>, _: Add) {
     ///         self.0 += 1;
     ///     }
     /// }
     ///
-    /// #[async_trait::async_trait]
     /// impl Handler<Get> for MyActor {
     ///     async fn handle(&mut self, ctx: &mut Context<Self>, _: Get) -> i32 {
     ///         self.0
     ///     }
     /// }
     ///
-    /// #[async_trait::async_trait]
     /// impl Handler<Die> for MyActor {
     ///     async fn handle(&mut self, ctx: &mut Context<Self>, _: Die) {
     ///         ctx.stop(None);
@@ -75,50 +86,271 @@ impl Supervisor {
         A: Actor,
         F: Fn() -> A + Send + 'static,
     {
-        let (mut ctx, mut rx, tx) = Context::new(None);
+        Self::start_with(ActorBuilder::default(), f).await
+    }
+
+    /// Start a supervisor with the actor configuration in `builder`.
+    ///
+    /// If `Actor::started` fails when the actor is restarted without a backoff delay, the supervisor gives up
+    /// instead of retrying in a busy loop, and the actor stops with that error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::sync::Arc;
+    /// use xactor::*;
+    ///
+    /// #[message]
+    /// struct Die;
+    ///
+    /// struct MyActor(Arc<AtomicBool>);
+    ///
+    /// impl Actor for MyActor {
+    ///     async fn started(&mut self, _ctx: &mut Context<Self>) -> Result<()> {
+    ///         // Only the first start succeeds
+    ///         if self.0.swap(true, Ordering::SeqCst) {
+    ///             return Err(error::anyhow!("cannot restart"));
+    ///         }
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// impl Handler<Die> for MyActor {
+    ///     async fn handle(&mut self, ctx: &mut Context<Self>, _: Die) {
+    ///         ctx.stop(None);
+    ///     }
+    /// }
+    ///
+    /// #[xactor::main(flavor = "current_thread")]
+    /// async fn main() -> Result<()> {
+    ///     let started = Arc::new(AtomicBool::new(false));
+    ///     let addr = Supervisor::start_with(ActorBuilder::new(), move || MyActor(started.clone())).await?;
+    ///
+    ///     addr.send(Die)?;
+    ///     let reason = addr.wait_for_stop().await;
+    ///     assert_eq!(reason.error().unwrap().to_string(), "cannot restart");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn start_with<A, F>(builder: ActorBuilder, f: F) -> Result<Addr<A>>
+    where
+        A: Actor,
+        F: Fn() -> A + Send + 'static,
+    {
+        let (tx_exit, rx_exit) = oneshot::channel();
+        let (mut ctx, mut rx, tx) = Context::new(Some(rx_exit.shared()), &builder);
         let addr = Addr {
             actor_id: ctx.actor_id(),
             tx,
             rx_exit: ctx.rx_exit.clone(),
         };
+        let mut restarts = RestartTracker::new(builder.supervisor_strategy);
 
         // Create the actor
         let mut actor = f();
 
         // Call started
         actor.started(&mut ctx).await?;
+        let seq = system::register(&addr);
+        trace::started::<A>(addr.actor_id);
 
         spawn({
             async move {
-                'restart_loop: loop {
-                    'event_loop: loop {
-                        match rx.next().await {
-                            None => break 'restart_loop,
-                            Some(ActorEvent::Stop(_err)) => break 'event_loop,
-                            Some(ActorEvent::Exec(f)) => f(&mut actor, &mut ctx).await,
-                            Some(ActorEvent::RemoveStream(id)) => {
-                                let mut streams = ctx.streams.lock().unwrap();
-
-                                if streams.contains(id) {
-                                    streams.remove(id);
+                let exit_reason = 'restart_loop: loop {
+                    let mut last_reason = run_event_loop(&mut actor, &mut ctx, &mut rx).await;
+                    let restart = !matches!(last_reason, ExitReason::AllAddressesDropped)
+                        && !system::is_shutting_down();
+                    if restart {
+                        rx.set_restarting();
+                    } else {
+                        rx.set_stopping();
+                    }
+                    stop_actor(&mut actor, &mut ctx).await;
+
+                    if !restart {
+                        break 'restart_loop last_reason;
+                    }
+
+                    loop {
+                        let delay = match restarts.next_delay() {
+                            Some(delay) => delay,
+                            None => break 'restart_loop last_reason,
+                        };
+                        if !delay.is_zero() {
+                            sleep(delay).await;
+                        }
+
+                        metrics::record_restart::<A>(ctx.actor_id());
+                        actor = f();
+                        match actor.started(&mut ctx).await {
+                            Ok(()) => {
+                                trace::restarted::<A>(ctx.actor_id());
+                                continue 'restart_loop;
+                            }
+                            Err(err) => {
+                                ctx.stop_children().await;
+                                ctx.abort_streams();
+                                ctx.abort_intervals();
+                                last_reason = ExitReason::Stopped(Arc::new(err));
+                                // Retrying without a delay would never yield to the mailbox
+                                if delay.is_zero() {
+                                    break 'restart_loop last_reason;
                                 }
                             }
                         }
                     }
+                };
 
-                    actor.stopped(&mut ctx).await;
-                    ctx.abort_streams();
-                    ctx.abort_intervals();
-
-                    actor = f();
-                    actor.started(&mut ctx).await.ok();
-                }
-                actor.stopped(&mut ctx).await;
-                ctx.abort_streams();
-                ctx.abort_intervals();
+                trace::stopped::<A>(ctx.actor_id(), &exit_reason);
+                system::unregister(seq, &exit_reason);
+                tx_exit.send(exit_reason).ok();
             }
         });
 
         Ok(addr)
     }
 }
+
+/// Restart policy used by `Supervisor`.
+///
+/// The default strategy restarts the actor immediately and forever, every time it stops.
+/// Without a backoff, a restart whose `Actor::started` fails is not retried: the actor stops with that error.
+///
+/// # Examples
+///
+/// ```rust
+/// use xactor::*;
+/// use std::time::Duration;
+///
+/// #[message]
+/// struct Die;
+///
+/// struct MyActor;
+///
+/// impl Actor for MyActor {}
+///
+/// impl Handler<Die> for MyActor {
+///     async fn handle(&mut self, ctx: &mut Context<Self>, _: Die) {
+///         ctx.stop(Some(error::anyhow!("died")));
+///     }
+/// }
+///
+/// #[xactor::main]
+/// async fn main() -> Result<()> {
+///     // Give up after 2 restarts within 10 seconds, waiting 10ms, 20ms, 40ms... (at most 1s) between restarts.
+///     let strategy = SupervisorStrategy::new()
+///         .max_restarts(2, Duration::from_secs(10))
+///         .backoff(Duration::from_millis(10), Duration::from_secs(1))
+///         .jitter(true);
+///     let addr = ActorBuilder::new()
+///         .supervisor_strategy(strategy)
+///         .start_supervised(|| MyActor)
+///         .await?;
+///
+///     for _ in 0..3 {
+///         addr.send_async(Die).await?;
+///     }
+///
+///     let reason = addr.wait_for_stop().await;
+///     assert_eq!(reason.error().unwrap().to_string(), "died");
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SupervisorStrategy {
+    max_restarts: Option<(usize, Duration)>,
+    backoff: Option<(Duration, Duration)>,
+    jitter: bool,
+}
+
+impl SupervisorStrategy {
+    /// Create a strategy that restarts the actor immediately and forever.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give up and stop the actor if it has to be restarted more than `count` times within `within`.
+    ///
+    /// A `count` of zero never restarts the actor.
+    pub fn max_restarts(mut self, count: usize, within: Duration) -> Self {
+        self.max_restarts = Some((count, within));
+        self
+    }
+
+    /// Wait before restarting the actor, starting with `initial` and doubling after each consecutive restart up to `max`.
+    ///
+    /// With a non-zero `initial` delay, a restart whose `Actor::started` fails is retried after the next delay.
+    /// The delay is reset once the restarted actor has been running for longer than `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = Some((initial, max));
+        self
+    }
+
+    /// Randomize each backoff delay to between half and all of its value,
+    /// so that actors failing together do not restart in lockstep.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+}
+
+pub(crate) struct RestartTracker {
+    strategy: SupervisorStrategy,
+    history: VecDeque<Instant>,
+    attempt: u32,
+    last_restart: Option<Instant>,
+}
+
+impl RestartTracker {
+    pub(crate) fn new(strategy: SupervisorStrategy) -> Self {
+        Self {
+            strategy,
+            history: VecDeque::new(),
+            attempt: 0,
+            last_restart: None,
+        }
+    }
+
+    /// Returns how long to wait before the next restart, or `None` to give up.
+    pub(crate) fn next_delay(&mut self) -> Option<Duration> {
+        let now = runtime::now();
+
+        if let Some((count, within)) = self.strategy.max_restarts {
+            while let Some(at) = self.history.front() {
+                if now.duration_since(*at) <= within {
+                    break;
+                }
+                self.history.pop_front();
+            }
+            if self.history.len() >= count {
+                return None;
+            }
+            self.history.push_back(now);
+        }
+
+        let (initial, max) = match self.strategy.backoff {
+            Some(backoff) => backoff,
+            None => return Some(Duration::ZERO),
+        };
+
+        if let Some(last_restart) = self.last_restart {
+            if now.saturating_duration_since(last_restart) > max {
+                self.attempt = 0;
+            }
+        }
+
+        let mut delay = initial
+            .checked_mul(2u32.saturating_pow(self.attempt))
+            .unwrap_or(max)
+            .min(max);
+        if self.strategy.jitter {
+            let factor = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+            delay = delay.mul_f64(0.5 + factor / 2.0);
+        }
+
+        self.attempt = self.attempt.saturating_add(1);
+        self.last_restart = Some(now + delay);
+        Some(delay)
+    }
+}
@@ -0,0 +1,470 @@
+use crate::actor::{run_event_loop, stop_actor};
+use crate::addr::{ActorEvent, ExitReason};
+use crate::error::Result;
+use crate::metrics;
+use crate::runtime::{sleep, spawn};
+use crate::supervisor::RestartTracker;
+use crate::system;
+use crate::trace;
+use crate::{Actor, ActorBuilder, ActorError, ActorId, Addr, Context, SupervisorStrategy};
+use futures::channel::{mpsc, oneshot};
+use futures::{Future, FutureExt, StreamExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Which children of a `SupervisorGroup` are restarted when one of them fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Only the failed child is restarted.
+    OneForOne,
+    /// All children are restarted.
+    OneForAll,
+    /// The failed child and all children added after it are restarted.
+    RestForOne,
+}
+
+enum Command {
+    Start(oneshot::Sender<Result<()>>),
+    Exit(ExitReason),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChildState {
+    Idle,
+    Running,
+    Stopping,
+    Gone,
+}
+
+struct ChildSpec {
+    actor_id: ActorId,
+    commands: mpsc::UnboundedSender<Command>,
+    stop: Box<dyn Fn() + Send>,
+    run: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+/// Supervises a group of cooperating actors of different types.
+///
+/// Children are started in the order they were added. When a child stops, the children selected by the
+/// `RestartPolicy` are stopped and restarted in that order. How often and how quickly this can happen is
+/// controlled by a `SupervisorStrategy`; once the group gives up, every child stops. `Addr::wait_for_stop` then
+/// returns the reason of the last failure for the child that failed, and `ActorError::ChildFailed` for the others.
+///
+/// A child whose addresses have all been dropped stops for good and is not restarted.
+///
+/// # Examples
+///
+/// ```rust
+/// use xactor::*;
+///
+/// #[message]
+/// struct Die;
+///
+/// #[message]
+/// struct Ping;
+///
+/// #[message(result = "i32")]
+/// struct Incr;
+///
+/// #[derive(Default)]
+/// struct Db;
+///
+/// impl Actor for Db {}
+///
+/// impl Handler<Die> for Db {
+///     async fn handle(&mut self, ctx: &mut Context<Self>, _: Die) {
+///         ctx.stop(None);
+///     }
+/// }
+///
+/// impl Handler<Ping> for Db {
+///     async fn handle(&mut self, _ctx: &mut Context<Self>, _: Ping) {}
+/// }
+///
+/// #[derive(Default)]
+/// struct Cache(i32);
+///
+/// impl Actor for Cache {}
+///
+/// impl Handler<Incr> for Cache {
+///     async fn handle(&mut self, _ctx: &mut Context<Self>, _: Incr) -> i32 {
+///         self.0 += 1;
+///         self.0
+///     }
+/// }
+///
+/// #[xactor::main]
+/// async fn main() -> Result<()> {
+///     let mut group = SupervisorGroup::new(RestartPolicy::RestForOne);
+///     let db = group.add(Db::default);
+///     let cache = group.add(Cache::default);
+///     group.start().await?;
+///
+///     assert_eq!(cache.call(Incr).await?, 1);
+///
+///     // The cache depends on the database, so it is restarted along with it
+///     db.call(Die).await?;
+///     // Queued behind the stop, so only handled once the database has been restarted
+///     db.call(Ping).await?;
+///     assert_eq!(cache.call(Incr).await?, 1);
+///     Ok(())
+/// }
+/// ```
+pub struct SupervisorGroup {
+    policy: RestartPolicy,
+    strategy: SupervisorStrategy,
+    children: Vec<ChildSpec>,
+    reports_tx: mpsc::UnboundedSender<(usize, ExitReason)>,
+    reports_rx: mpsc::UnboundedReceiver<(usize, ExitReason)>,
+}
+
+impl SupervisorGroup {
+    /// Create an empty group restarting its children according to `policy`.
+    pub fn new(policy: RestartPolicy) -> Self {
+        let (reports_tx, reports_rx) = mpsc::unbounded();
+        Self {
+            policy,
+            strategy: SupervisorStrategy::default(),
+            children: Vec::new(),
+            reports_tx,
+            reports_rx,
+        }
+    }
+
+    /// Set how often and how quickly the group restarts its children.
+    pub fn strategy(mut self, strategy: SupervisorStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Add a child created by `f`, returning its address.
+    ///
+    /// The address stays valid across restarts. Messages sent before the group is started are queued.
+    pub fn add<A, F>(&mut self, f: F) -> Addr<A>
+    where
+        A: Actor,
+        F: Fn() -> A + Send + 'static,
+    {
+        self.add_with(ActorBuilder::default(), f)
+    }
+
+    /// Add a child created by `f` with the actor configuration in `builder`, returning its address.
+    pub fn add_with<A, F>(&mut self, builder: ActorBuilder, f: F) -> Addr<A>
+    where
+        A: Actor,
+        F: Fn() -> A + Send + 'static,
+    {
+        let (tx_exit, rx_exit) = oneshot::channel();
+        let (mut ctx, mut rx, tx) = Context::new(Some(rx_exit.shared()), &builder);
+        let addr = Addr {
+            actor_id: ctx.actor_id(),
+            tx,
+            rx_exit: ctx.rx_exit.clone(),
+        };
+
+        let index = self.children.len();
+        let reports = self.reports_tx.clone();
+        let (commands_tx, mut commands) = mpsc::unbounded();
+        let weak_addr = addr.downgrade();
+        let stop_addr = weak_addr.clone();
+
+        let run = async move {
+            let mut seq = None;
+            let exit_reason = loop {
+                match commands.next().await {
+                    Some(Command::Start(done)) => {
+                        if seq.is_some() {
+                            metrics::record_restart::<A>(ctx.actor_id());
+                        }
+                        let mut actor = f();
+                        if let Err(err) = actor.started(&mut ctx).await {
+                            ctx.stop_children().await;
+                            ctx.abort_streams();
+                            ctx.abort_intervals();
+                            done.send(Err(err)).ok();
+                            continue;
+                        }
+                        done.send(Ok(())).ok();
+                        if seq.is_none() {
+                            if let Some(addr) = weak_addr.upgrade() {
+                                seq = Some(system::register(&addr));
+                                trace::started::<A>(ctx.actor_id());
+                            }
+                        } else {
+                            trace::restarted::<A>(ctx.actor_id());
+                        }
+
+                        let reason = run_event_loop(&mut actor, &mut ctx, &mut rx).await;
+                        if let ExitReason::AllAddressesDropped = reason {
+                            rx.set_stopping();
+                        } else {
+                            rx.set_restarting();
+                        }
+                        stop_actor(&mut actor, &mut ctx).await;
+
+                        reports.unbounded_send((index, reason.clone())).ok();
+                        if let ExitReason::AllAddressesDropped = reason {
+                            break reason;
+                        }
+                    }
+                    Some(Command::Exit(reason)) => break reason,
+                    None => break ExitReason::Normal,
+                }
+            };
+
+            if let Some(seq) = seq {
+                trace::stopped::<A>(ctx.actor_id(), &exit_reason);
+                system::unregister(seq, &exit_reason);
+            }
+            tx_exit.send(exit_reason).ok();
+        };
+
+        self.children.push(ChildSpec {
+            actor_id: addr.actor_id,
+            commands: commands_tx,
+            stop: Box::new(move || {
+                if let Some(addr) = stop_addr.upgrade() {
+                    addr.tx.send_event(ActorEvent::Stop(None)).ok();
+                }
+            }),
+            run: Box::pin(run),
+        });
+        addr
+    }
+
+    /// Start all children in the order they were added.
+    ///
+    /// If a child fails to start, every child is stopped and `ActorError::ChildFailed` is returned.
+    /// `Addr::wait_for_stop` returns the error of `Actor::started` for the child that failed,
+    /// and `ActorError::ChildFailed` for the others.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use xactor::*;
+    ///
+    /// #[derive(Default)]
+    /// struct Db;
+    ///
+    /// impl Actor for Db {}
+    ///
+    /// #[derive(Default)]
+    /// struct Cache;
+    ///
+    /// impl Actor for Cache {
+    ///     async fn started(&mut self, _ctx: &mut Context<Self>) -> Result<()> {
+    ///         Err(error::anyhow!("no memory"))
+    ///     }
+    /// }
+    ///
+    /// #[xactor::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut group = SupervisorGroup::new(RestartPolicy::OneForAll);
+    ///     let db = group.add(Db::default);
+    ///     let cache = group.add(Cache::default);
+    ///     let cache_id = cache.actor_id();
+    ///
+    ///     let err = group.start().await.unwrap_err();
+    ///     assert_eq!(err.to_string(), format!("child actor {} failed: no memory", cache_id));
+    ///
+    ///     let reason = cache.wait_for_stop().await;
+    ///     assert_eq!(reason.error().unwrap().to_string(), "no memory");
+    ///
+    ///     let reason = db.wait_for_stop().await;
+    ///     match reason.error().unwrap().downcast_ref::<ActorError>() {
+    ///         Some(ActorError::ChildFailed { actor_id, .. }) => assert_eq!(*actor_id, cache_id),
+    ///         err => panic!("unexpected error: {:?}", err),
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn start(self) -> Result<()> {
+        let Self {
+            policy,
+            strategy,
+            children,
+            reports_tx,
+            reports_rx,
+        } = self;
+        drop(reports_tx);
+
+        let mut actor_ids = Vec::with_capacity(children.len());
+        let mut commands = Vec::with_capacity(children.len());
+        let mut stops = Vec::with_capacity(children.len());
+        for child in children {
+            spawn(child.run);
+            actor_ids.push(child.actor_id);
+            commands.push(child.commands);
+            stops.push(child.stop);
+        }
+
+        let mut coordinator = Coordinator {
+            policy,
+            restarts: RestartTracker::new(strategy),
+            states: vec![ChildState::Idle; commands.len()],
+            actor_ids,
+            commands,
+            stops,
+            reports: reports_rx,
+            pending: VecDeque::new(),
+        };
+
+        for index in 0..coordinator.commands.len() {
+            if let Err(err) = coordinator.start_child(index).await {
+                let reason = ExitReason::Stopped(Arc::new(err));
+                coordinator.shutdown(index, reason.clone()).await;
+                return Err(ActorError::ChildFailed {
+                    actor_id: coordinator.actor_ids[index],
+                    reason,
+                }
+                .into());
+            }
+        }
+
+        spawn(coordinator.run());
+        Ok(())
+    }
+}
+
+struct Coordinator {
+    policy: RestartPolicy,
+    restarts: RestartTracker,
+    states: Vec<ChildState>,
+    actor_ids: Vec<ActorId>,
+    commands: Vec<mpsc::UnboundedSender<Command>>,
+    stops: Vec<Box<dyn Fn() + Send>>,
+    reports: mpsc::UnboundedReceiver<(usize, ExitReason)>,
+    pending: VecDeque<(usize, ExitReason)>,
+}
+
+impl Coordinator {
+    async fn run(mut self) {
+        loop {
+            let (index, reason) = match self.pending.pop_front() {
+                Some(report) => report,
+                None => match self.reports.next().await {
+                    Some(report) => report,
+                    None => return,
+                },
+            };
+            self.states[index] = ChildState::Idle;
+
+            if let ExitReason::AllAddressesDropped = reason {
+                self.states[index] = ChildState::Gone;
+                continue;
+            }
+
+            if system::is_shutting_down() {
+                self.shutdown(index, reason).await;
+                return;
+            }
+
+            let delay = match self.restarts.next_delay() {
+                Some(delay) => delay,
+                None => {
+                    self.shutdown(index, reason).await;
+                    return;
+                }
+            };
+
+            let restart: Vec<usize> = match self.policy {
+                RestartPolicy::OneForOne => vec![index],
+                RestartPolicy::OneForAll => (0..self.states.len()).collect(),
+                RestartPolicy::RestForOne => (index..self.states.len()).collect(),
+            };
+
+            self.stop_children(&restart).await;
+            self.pending.retain(|(index, _)| !restart.contains(index));
+
+            if !delay.is_zero() {
+                sleep(delay).await;
+            }
+
+            for index in restart {
+                if self.states[index] == ChildState::Idle {
+                    if let Err(err) = self.start_child(index).await {
+                        self.pending
+                            .push_back((index, ExitReason::Stopped(Arc::new(err))));
+                    }
+                }
+            }
+        }
+    }
+
+    async fn start_child(&mut self, index: usize) -> Result<()> {
+        let (done_tx, done_rx) = oneshot::channel();
+        self.commands[index]
+            .unbounded_send(Command::Start(done_tx))
+            .ok();
+        match done_rx.await {
+            Ok(Ok(())) => {
+                self.states[index] = ChildState::Running;
+                Ok(())
+            }
+            Ok(Err(err)) => Err(err),
+            Err(_) => {
+                self.states[index] = ChildState::Gone;
+                Ok(())
+            }
+        }
+    }
+
+    /// Stop the running children in `indices` and wait for them to finish.
+    ///
+    /// Children outside of `indices` that stop in the meantime are queued to be handled afterwards.
+    async fn stop_children(&mut self, indices: &[usize]) {
+        for &index in indices.iter().rev() {
+            if self.states[index] == ChildState::Running {
+                self.states[index] = ChildState::Stopping;
+                (self.stops[index])();
+            }
+        }
+
+        while indices
+            .iter()
+            .any(|index| self.states[*index] == ChildState::Stopping)
+        {
+            let (index, reason) = match self.reports.next().await {
+                Some(report) => report,
+                None => return,
+            };
+            let state = match reason {
+                ExitReason::AllAddressesDropped => ChildState::Gone,
+                _ => ChildState::Idle,
+            };
+            let expected = self.states[index] == ChildState::Stopping;
+            self.states[index] = state;
+            if !expected && state == ChildState::Idle {
+                self.pending.push_back((index, reason));
+            }
+        }
+    }
+
+    /// Stop every child for good, because the child at `index` stopped with `reason`.
+    ///
+    /// That child exits with `reason`. If it is a failure, the others exit with `ActorError::ChildFailed`,
+    /// otherwise with `reason` too.
+    async fn shutdown(&mut self, index: usize, reason: ExitReason) {
+        let all: Vec<usize> = (0..self.states.len()).collect();
+        self.stop_children(&all).await;
+
+        let sibling_reason = if reason.is_failure() {
+            let err = ActorError::ChildFailed {
+                actor_id: self.actor_ids[index],
+                reason: reason.clone(),
+            };
+            ExitReason::Stopped(Arc::new(err.into()))
+        } else {
+            reason.clone()
+        };
+        for (i, commands) in self.commands.iter().enumerate() {
+            let reason = if i == index {
+                reason.clone()
+            } else {
+                sibling_reason.clone()
+            };
+            commands.unbounded_send(Command::Exit(reason)).ok();
+        }
+    }
+}
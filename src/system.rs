@@ -0,0 +1,332 @@
+use crate::addr::{ActorEvent, ExitReason, ExitReceiver};
+use crate::mailbox::MailboxStats;
+use crate::runtime::{spawn, timeout};
+use crate::{Actor, ActorId, Addr, Result};
+use once_cell::sync::OnceCell;
+use std::any::type_name;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+
+struct RunningActor {
+    actor_id: ActorId,
+    /// Only recorded once `System::enable_introspection` has been called.
+    details: Option<Details>,
+    stats: Arc<MailboxStats>,
+    stop: Arc<dyn Fn() + Send + Sync>,
+    rx_exit: Option<ExitReceiver>,
+}
+
+struct Details {
+    type_name: &'static str,
+    name: Option<Arc<str>>,
+    started_at: SystemTime,
+}
+
+/// What a running actor is doing, as reported by `System::actors`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActorStatus {
+    /// Waiting for its next message.
+    Idle,
+    /// Handling a message.
+    Handling,
+    /// Stopped by a failure, waiting for its supervisor to restart it.
+    Restarting,
+    /// Being stopped, with its `stopped` method running.
+    Stopping,
+}
+
+/// A running actor, as reported by `System::actors`.
+#[derive(Debug, Clone)]
+pub struct ActorInfo {
+    /// The id of the actor.
+    pub actor_id: ActorId,
+    /// The type name of the actor.
+    pub type_name: &'static str,
+    /// The name given with `ActorBuilder::name`.
+    pub name: Option<Arc<str>>,
+    /// When the actor finished starting.
+    pub started_at: SystemTime,
+    /// The number of messages waiting in its mailbox.
+    pub mailbox_len: usize,
+    /// What the actor is doing.
+    pub status: ActorStatus,
+}
+
+/// Running actors, keyed by the order in which they finished starting.
+///
+/// Every actor is recorded, whether introspection is enabled or not, so that `System::shutdown` can stop them all.
+/// The mailbox stats it shares are also used by the `testing` helpers.
+static RUNNING: OnceCell<Mutex<BTreeMap<usize, RunningActor>>> = OnceCell::new();
+/// Actors that stopped because they panicked, for `#[xactor::testing::test(assert_stopped)]`.
+static PANICKED: OnceCell<Mutex<Vec<ActorId>>> = OnceCell::new();
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+static INTROSPECTION: AtomicBool = AtomicBool::new(false);
+static SHUTDOWN_DONE: OnceCell<watch::Sender<bool>> = OnceCell::new();
+
+fn running() -> &'static Mutex<BTreeMap<usize, RunningActor>> {
+    RUNNING.get_or_init(Default::default)
+}
+
+fn panicked() -> &'static Mutex<Vec<ActorId>> {
+    PANICKED.get_or_init(Default::default)
+}
+
+fn shutdown_done() -> &'static watch::Sender<bool> {
+    SHUTDOWN_DONE.get_or_init(|| watch::channel(false).0)
+}
+
+/// Record a started actor, returning the key to pass to `unregister` once it has stopped.
+pub(crate) fn register<A: Actor>(addr: &Addr<A>) -> usize {
+    let weak_addr = addr.downgrade();
+    register_with::<A>(
+        addr.actor_id,
+        addr.tx.name.clone(),
+        addr.tx.stats.clone(),
+        Arc::new(move || {
+            if let Some(addr) = weak_addr.upgrade() {
+                addr.tx.send_event(ActorEvent::Stop(None)).ok();
+            }
+        }),
+        addr.rx_exit.clone(),
+    )
+}
+
+/// Record a started actor stopped by calling `stop`, for actors without an `Addr`.
+pub(crate) fn register_with<A>(
+    actor_id: ActorId,
+    name: Option<Arc<str>>,
+    stats: Arc<MailboxStats>,
+    stop: Arc<dyn Fn() + Send + Sync>,
+    rx_exit: Option<ExitReceiver>,
+) -> usize {
+    static SEQ: OnceCell<AtomicUsize> = OnceCell::new();
+    let seq = SEQ
+        .get_or_init(Default::default)
+        .fetch_add(1, Ordering::Relaxed);
+
+    let details = INTROSPECTION.load(Ordering::Relaxed).then(|| Details {
+        type_name: type_name::<A>(),
+        name,
+        started_at: SystemTime::now(),
+    });
+    running().lock().unwrap().insert(
+        seq,
+        RunningActor {
+            actor_id,
+            details,
+            stats,
+            stop,
+            rx_exit,
+        },
+    );
+    seq
+}
+
+/// Remove a stopped actor, remembering it if it panicked.
+pub(crate) fn unregister(seq: usize, reason: &ExitReason) {
+    let actor = running().lock().unwrap().remove(&seq);
+    if let (Some(actor), ExitReason::Panicked) = (actor, reason) {
+        panicked().lock().unwrap().push(actor.actor_id);
+    }
+}
+
+/// Returns the ids of the actors that stopped because they panicked since the last call.
+pub(crate) fn take_panicked() -> Vec<ActorId> {
+    std::mem::take(&mut *panicked().lock().unwrap())
+}
+
+/// Forget every actor and reset the shutdown state, so that the system can be used from a new runtime.
+pub(crate) fn reset() {
+    running().lock().unwrap().clear();
+    panicked().lock().unwrap().clear();
+    SHUTTING_DOWN.store(false, Ordering::Relaxed);
+    shutdown_done().send_replace(false);
+}
+
+/// Returns `true` if a running actor has messages waiting that it has not picked up yet.
+pub(crate) fn has_ready_actors() -> bool {
+    running()
+        .lock()
+        .unwrap()
+        .values()
+        .any(|actor| actor.stats.is_ready())
+}
+
+/// Returns `true` while `System::shutdown` is stopping actors, so that supervisors do not restart them.
+pub(crate) fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Relaxed)
+}
+
+/// The actor system.
+///
+/// Keeps track of every running actor and service, so that they can all be stopped gracefully,
+/// with their `stopped` methods called, before the program exits.
+/// `#[xactor::main]` shuts the system down with `System::DEFAULT_SHUTDOWN_TIMEOUT` once `main` returns.
+///
+/// # Examples
+///
+/// ```rust
+/// use xactor::*;
+///
+/// #[message]
+/// struct Quit;
+///
+/// struct MyActor;
+///
+/// impl Actor for MyActor {
+///     async fn stopped(&mut self, _ctx: &mut Context<Self>) {
+///         println!("stopped");
+///     }
+/// }
+///
+/// impl Handler<Quit> for MyActor {
+///     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Quit) {
+///         System::request_shutdown();
+///     }
+/// }
+///
+/// #[xactor::main]
+/// async fn main() -> Result<()> {
+///     let addr = MyActor.start().await?;
+///     addr.send(Quit)?;
+///
+///     // Returns once every actor has been stopped
+///     System::wait_for_shutdown().await;
+///     assert!(System::running_actors().is_empty());
+///     Ok(())
+/// }
+/// ```
+pub struct System;
+
+impl System {
+    /// The deadline used by `request_shutdown` and `#[xactor::main]`.
+    pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Stop all running actors and services, in the reverse order they were started,
+    /// waiting for each one to finish before stopping the next.
+    ///
+    /// Supervised actors are not restarted while the system is shutting down.
+    /// Fails with `ActorError::Timeout` if the actors have not all stopped within `deadline`.
+    ///
+    /// This must not be awaited from a message handler, since the actor could not stop while handling
+    /// the message: use `request_shutdown` instead.
+    pub async fn shutdown(deadline: Duration) -> Result<()> {
+        SHUTTING_DOWN.store(true, Ordering::Relaxed);
+
+        let res = timeout(deadline, async {
+            loop {
+                let last = running()
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .next_back()
+                    .map(|(seq, actor)| (*seq, actor.stop.clone(), actor.rx_exit.clone()));
+                let (seq, stop, rx_exit) = match last {
+                    Some(last) => last,
+                    None => break,
+                };
+
+                stop();
+                if let Some(rx_exit) = rx_exit {
+                    rx_exit.await.ok();
+                }
+                // Normally already done by the actor itself
+                running().lock().unwrap().remove(&seq);
+            }
+        })
+        .await;
+
+        crate::service::clear_registry().await;
+        crate::registry::clear();
+        SHUTTING_DOWN.store(false, Ordering::Relaxed);
+        shutdown_done().send_replace(true);
+
+        res?;
+        Ok(())
+    }
+
+    /// Shut the system down in the background, with `DEFAULT_SHUTDOWN_TIMEOUT` as the deadline.
+    ///
+    /// Unlike `shutdown`, this can be called from a message handler.
+    pub fn request_shutdown() {
+        spawn(async {
+            Self::shutdown(Self::DEFAULT_SHUTDOWN_TIMEOUT).await.ok();
+        });
+    }
+
+    /// Wait until the system has been shut down.
+    ///
+    /// Returns immediately if it has already been shut down.
+    pub async fn wait_for_shutdown() {
+        shutdown_done()
+            .subscribe()
+            .wait_for(|done| *done)
+            .await
+            .ok();
+    }
+
+    /// Returns the ids of the running actors, in the order they were started.
+    pub fn running_actors() -> Vec<ActorId> {
+        running()
+            .lock()
+            .unwrap()
+            .values()
+            .map(|actor| actor.actor_id)
+            .collect()
+    }
+
+    /// Record the type, name and start time of every actor started from now on, to be listed by `actors`.
+    ///
+    /// Running actors are always tracked for `System::shutdown`, along with their mailbox length and status:
+    /// this only adds those details, and costs nothing until it is called.
+    pub fn enable_introspection() {
+        INTROSPECTION.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the running actors started since `enable_introspection` was called, in the order they were started.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use xactor::*;
+    ///
+    /// struct MyActor;
+    ///
+    /// impl Actor for MyActor {}
+    ///
+    /// #[xactor::main]
+    /// async fn main() -> Result<()> {
+    ///     System::enable_introspection();
+    ///     let addr = ActorBuilder::new().name("worker").start(MyActor).await?;
+    ///
+    ///     let actors = System::actors();
+    ///     assert_eq!(actors.len(), 1);
+    ///     assert_eq!(actors[0].actor_id, addr.actor_id());
+    ///     assert_eq!(actors[0].type_name, std::any::type_name::<MyActor>());
+    ///     assert_eq!(actors[0].name.as_deref(), Some("worker"));
+    ///     assert_eq!(actors[0].status, ActorStatus::Idle);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn actors() -> Vec<ActorInfo> {
+        running()
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|actor| {
+                let details = actor.details.as_ref()?;
+                Some(ActorInfo {
+                    actor_id: actor.actor_id,
+                    type_name: details.type_name,
+                    name: details.name.clone(),
+                    started_at: details.started_at,
+                    mailbox_len: actor.stats.len(),
+                    status: actor.stats.status(),
+                })
+            })
+            .collect()
+    }
+}
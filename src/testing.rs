@@ -0,0 +1,388 @@
+//! Helpers for testing actors.
+//!
+//! Timers can be tested deterministically by pausing the clock: `sleep`, `timeout`,
+//! `Context::send_later`, `Context::send_interval` and supervisor backoffs then only
+//! make progress when the test calls `advance`.
+//!
+//! The clock is shared by the whole process, so it can only be paused inside `run_test`,
+//! which keeps other tests from running at the same time.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use xactor::*;
+//!
+//! #[message]
+//! #[derive(Clone)]
+//! struct Tick;
+//!
+//! #[message(result = "u32")]
+//! struct GetTicks;
+//!
+//! #[derive(Default)]
+//! struct MyActor(u32);
+//!
+//! impl Actor for MyActor {
+//!     async fn started(&mut self, ctx: &mut Context<Self>) -> Result<()> {
+//!         ctx.send_interval(Tick, Duration::from_secs(60));
+//!         Ok(())
+//!     }
+//! }
+//!
+//! impl Handler<Tick> for MyActor {
+//!     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Tick) {
+//!         self.0 += 1;
+//!     }
+//! }
+//!
+//! impl Handler<GetTicks> for MyActor {
+//!     async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: GetTicks) -> u32 {
+//!         self.0
+//!     }
+//! }
+//!
+//! fn main() -> Result<()> {
+//!     testing::run_test(RuntimeOptions::new().current_thread(), false, async {
+//!         testing::pause();
+//!         let addr = MyActor::start_default().await?;
+//!
+//!         // Three hours pass instantly
+//!         testing::advance(Duration::from_secs(3 * 60 * 60)).await;
+//!         assert_eq!(addr.call(GetTicks).await?, 180);
+//!         Ok(())
+//!     })
+//! }
+//! ```
+
+mod probe;
+
+pub use probe::TestProbe;
+pub use xactor_derive::test;
+
+use crate::runtime::{block_on_with, sleep, yield_now};
+use crate::{registry, service, system, Actor, Addr, RuntimeOptions, System};
+use futures::task::{Context, Poll, Waker};
+use futures::Future;
+use std::collections::{BTreeMap, HashSet};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// How long actors whose addresses were dropped by the test are given to stop.
+const STOP_GRACE_PERIOD: Duration = Duration::from_millis(100);
+
+/// How many times in a row the actors must be seen idle before they are considered settled.
+const SETTLE_ROUNDS: usize = 16;
+
+struct Clock {
+    /// The real time at which the clock was paused.
+    start: Instant,
+    /// The virtual time elapsed since then.
+    elapsed: Duration,
+    next_id: u64,
+    timers: BTreeMap<(Duration, u64), Option<Waker>>,
+    /// Timers that have elapsed, but whose task has not run yet.
+    fired: HashSet<u64>,
+}
+
+impl Clock {
+    /// Fire the earliest timers if they elapse by `until`, moving the clock to their deadline.
+    ///
+    /// Returns `false` if there is no such timer.
+    fn fire_next(&mut self, until: Duration) -> bool {
+        let deadline = match self.timers.keys().next() {
+            Some((deadline, _)) if *deadline <= until => *deadline,
+            _ => return false,
+        };
+        self.elapsed = self.elapsed.max(deadline);
+        while let Some(entry) = self.timers.first_entry() {
+            if entry.key().0 > deadline {
+                break;
+            }
+            let ((_, id), waker) = entry.remove_entry();
+            self.fired.insert(id);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+        true
+    }
+}
+
+/// The virtual clock, `None` unless it is paused.
+static CLOCK: Mutex<Option<Clock>> = Mutex::new(None);
+
+/// Set while the clock is paused, so that timers only lock `CLOCK` in tests that pause it.
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+fn clock() -> MutexGuard<'static, Option<Clock>> {
+    CLOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Set while `run_test` runs a test, the only time the clock can be paused.
+static IN_TEST: AtomicBool = AtomicBool::new(false);
+
+/// Clears `IN_TEST` and resumes the clock once the test returns or panics.
+struct TestGuard;
+
+impl Drop for TestGuard {
+    fn drop(&mut self) {
+        resume();
+        IN_TEST.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Pause the clock.
+///
+/// Time stops for `sleep`, `timeout`, `Context::send_later`, `Context::send_interval` and
+/// supervisor backoffs on every thread, until it is moved forward with `advance`.
+/// The clock is resumed when the test returns.
+/// Pausing an already paused clock does nothing.
+///
+/// # Panics
+///
+/// Panics if called outside `run_test`, such as in a `#[test]` that does not use `#[xactor::testing::test]`,
+/// since pausing the clock would freeze the timers of the tests running in parallel.
+pub fn pause() {
+    assert!(
+        IN_TEST.load(Ordering::SeqCst),
+        "the clock can only be paused in a test run by `testing::run_test`, such as `#[xactor::testing::test]`"
+    );
+    let mut clock = clock();
+    if clock.is_none() {
+        *clock = Some(Clock {
+            start: Instant::now(),
+            elapsed: Duration::ZERO,
+            next_id: 0,
+            timers: BTreeMap::new(),
+            fired: HashSet::new(),
+        });
+        PAUSED.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Resume the clock, completing every timer that was waiting for the paused clock immediately.
+pub fn resume() {
+    if !PAUSED.load(Ordering::SeqCst) {
+        return;
+    }
+    let mut clock = clock();
+    PAUSED.store(false, Ordering::SeqCst);
+    if let Some(clock) = clock.take() {
+        for waker in clock.timers.into_values().flatten() {
+            waker.wake();
+        }
+    }
+}
+
+/// Move the paused clock forward by `duration`.
+///
+/// Timers elapse in order: before each one fires, the actors are given the chance to handle all their
+/// messages (see `drain_all`), so that the timers they start in the meantime are taken into account.
+///
+/// # Panics
+///
+/// Panics if the clock is not paused.
+pub async fn advance(duration: Duration) {
+    let until = match &*clock() {
+        Some(clock) => clock.elapsed + duration,
+        None => panic!("the clock must be paused with `testing::pause` before it can advance"),
+    };
+
+    loop {
+        drain_all().await;
+        let fired = match &mut *clock() {
+            Some(clock) => clock.fire_next(until),
+            None => return,
+        };
+        if !fired {
+            break;
+        }
+    }
+
+    if let Some(clock) = &mut *clock() {
+        clock.elapsed = until;
+    }
+    drain_all().await;
+}
+
+/// Wait until the actor at `addr` has handled every message in its mailbox, or has stopped.
+///
+/// This does not move a paused clock, so it does not return while a handler waits for a timer.
+pub async fn drain<A: Actor>(addr: &Addr<A>) {
+    loop {
+        if !addr.is_alive() || addr.tx.stats.is_idle() {
+            return;
+        }
+        yield_now().await;
+    }
+}
+
+/// Wait until no running actor has messages left to pick up, and every elapsed timer has been seen by its task.
+///
+/// This is a best-effort heuristic rather than a guarantee: it returns once the actors have been seen idle
+/// for a number of scheduler rounds in a row. Actors awaiting inside a handler, for a paused clock or for
+/// anything else, count as idle, and so do tasks spawned outside of any actor.
+/// It is only reliable on a single-threaded runtime, such as
+/// `#[xactor::testing::test(flavor = "current_thread")]`: on a multi-threaded one, a handler that is still running
+/// on another thread may send messages after this has returned.
+pub async fn drain_all() {
+    let mut idle_rounds = 0;
+    while idle_rounds < SETTLE_ROUNDS {
+        yield_now().await;
+        let timers_fired = clock()
+            .as_ref()
+            .is_some_and(|clock| !clock.fired.is_empty());
+        if timers_fired || system::has_ready_actors() {
+            idle_rounds = 0;
+        } else {
+            idle_rounds += 1;
+        }
+    }
+}
+
+/// A timer of the paused clock.
+pub(crate) struct VirtualSleep {
+    id: u64,
+    deadline: Duration,
+}
+
+impl Future for VirtualSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut clock = clock();
+        let clock = match &mut *clock {
+            Some(clock) => clock,
+            // The clock was resumed
+            None => return Poll::Ready(()),
+        };
+        if clock.fired.remove(&self.id) || clock.elapsed >= self.deadline {
+            clock.timers.remove(&(self.deadline, self.id));
+            return Poll::Ready(());
+        }
+        clock
+            .timers
+            .insert((self.deadline, self.id), Some(cx.waker().clone()));
+        Poll::Pending
+    }
+}
+
+impl Drop for VirtualSleep {
+    fn drop(&mut self) {
+        if let Some(clock) = &mut *clock() {
+            clock.timers.remove(&(self.deadline, self.id));
+            clock.fired.remove(&self.id);
+        }
+    }
+}
+
+/// Returns a timer of the paused clock, or `None` if the clock is not paused.
+pub(crate) fn virtual_sleep(duration: Duration) -> Option<VirtualSleep> {
+    if !PAUSED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let mut clock = clock();
+    let clock = clock.as_mut()?;
+    let id = clock.next_id;
+    clock.next_id += 1;
+    let deadline = clock.elapsed + duration;
+    clock.timers.insert((deadline, id), None);
+    Some(VirtualSleep { id, deadline })
+}
+
+/// Returns the time of the paused clock, or `None` if the clock is not paused.
+pub(crate) fn virtual_now() -> Option<Instant> {
+    if !PAUSED.load(Ordering::Relaxed) {
+        return None;
+    }
+    clock().as_ref().map(|clock| clock.start + clock.elapsed)
+}
+
+/// Run the body of a test, as generated by `#[xactor::testing::test]`.
+///
+/// Tests run with this function are serialized, and each one starts with an empty service registry,
+/// an empty `Registry`, no running actors and a running clock, so that they cannot observe each other's
+/// services or timers. Once `future` returns, all running actors are stopped with `System::shutdown`.
+///
+/// This state is global to the process, so it is reset under the feet of any test running at the same time
+/// without this function. Every test of the binary that uses actors must therefore be run with it,
+/// usually through `#[xactor::testing::test]`.
+///
+/// If `assert_stopped` is set, this panics if any actor is still running when `future` returns,
+/// or if any actor stopped because one of its handlers panicked.
+///
+/// # Examples
+///
+/// ```rust,should_panic
+/// use xactor::*;
+///
+/// struct MyActor;
+///
+/// impl Actor for MyActor {}
+///
+/// fn main() {
+///     xactor::testing::run_test(RuntimeOptions::new(), true, async {
+///         let addr = MyActor.start().await.unwrap();
+///         // Leaked, so still running when the test returns
+///         std::mem::forget(addr);
+///     });
+/// }
+/// ```
+pub fn run_test<F, T>(options: RuntimeOptions, assert_stopped: bool, future: F) -> T
+where
+    F: Future<Output = T>,
+{
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+    let _lock = TEST_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    IN_TEST.store(true, Ordering::SeqCst);
+    let _guard = TestGuard;
+
+    block_on_with(options, async move {
+        resume();
+        system::reset();
+        service::clear_registry().await;
+        registry::clear();
+
+        let res = future.await;
+        resume();
+
+        let running = if assert_stopped {
+            wait_for_actors(STOP_GRACE_PERIOD).await
+        } else {
+            Vec::new()
+        };
+        System::shutdown(System::DEFAULT_SHUTDOWN_TIMEOUT)
+            .await
+            .ok();
+        let panicked = system::take_panicked();
+
+        if assert_stopped {
+            assert!(
+                running.is_empty(),
+                "actors still running when the test returned: {:?}",
+                running
+            );
+            assert!(
+                panicked.is_empty(),
+                "actors stopped because a handler panicked: {:?}",
+                panicked
+            );
+        }
+        res
+    })
+}
+
+/// Wait up to `grace_period` for all actors to stop, returning the ones still running.
+async fn wait_for_actors(grace_period: Duration) -> Vec<crate::ActorId> {
+    let deadline = Instant::now() + grace_period;
+    loop {
+        let running = System::running_actors();
+        if running.is_empty() || Instant::now() >= deadline {
+            return running;
+        }
+        sleep(Duration::from_millis(1)).await;
+    }
+}
@@ -0,0 +1,221 @@
+use crate::context::next_actor_id;
+use crate::runtime::timeout;
+use crate::{ActorError, ActorId, Caller, Message, Result, Sender};
+use futures::channel::{mpsc, oneshot};
+use futures::{Future, StreamExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long `TestProbe::expect_msg` waits for a message.
+const DEFAULT_EXPECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+struct Replies<T: Message> {
+    reply_with: Option<Box<dyn Fn(&T) -> T::Result + Send>>,
+    pending: VecDeque<oneshot::Sender<T::Result>>,
+}
+
+/// A stand-in for an actor, recording the messages it receives.
+///
+/// A probe can be turned into a `Sender<T>` or a `Caller<T>`, and handed to the code under test instead of a
+/// real actor. Calls are answered by the function set with `reply_with`, or wait for the test to `reply`.
+///
+/// The `expect_*` methods follow the clock, so they only time out after `testing::advance` while it is paused.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use xactor::testing::TestProbe;
+/// use xactor::*;
+///
+/// #[message(result = "i32")]
+/// struct Double(i32);
+///
+/// #[message]
+/// struct Done(i32);
+///
+/// struct Worker {
+///     doubler: Caller<Double>,
+///     done: Sender<Done>,
+/// }
+///
+/// impl Actor for Worker {
+///     async fn started(&mut self, _ctx: &mut Context<Self>) -> Result<()> {
+///         let res = self.doubler.call(Double(21)).await?;
+///         self.done.send(Done(res))
+///     }
+/// }
+///
+/// #[xactor::main]
+/// async fn main() -> Result<()> {
+///     let mut doubler = TestProbe::new();
+///     doubler.reply_with(|msg: &Double| msg.0 * 2);
+///     let mut done = TestProbe::new();
+///
+///     let _worker = Worker {
+///         doubler: doubler.caller(),
+///         done: done.sender(),
+///     }
+///     .start()
+///     .await?;
+///
+///     assert_eq!(doubler.expect_msg().await.0, 21);
+///     assert_eq!(done.expect_msg().await.0, 42);
+///     done.expect_no_msg(Duration::from_millis(100)).await;
+///     Ok(())
+/// }
+/// ```
+pub struct TestProbe<T: Message> {
+    actor_id: ActorId,
+    tx: mpsc::UnboundedSender<T>,
+    rx: mpsc::UnboundedReceiver<T>,
+    replies: Arc<Mutex<Replies<T>>>,
+}
+
+impl<T: Message> Default for TestProbe<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Message> TestProbe<T> {
+    /// Create a probe that has not received any message.
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::unbounded();
+        Self {
+            actor_id: next_actor_id(),
+            tx,
+            rx,
+            replies: Arc::new(Mutex::new(Replies {
+                reply_with: None,
+                pending: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Returns the id of the probe, shared by its `Sender<T>`s and `Caller<T>`s.
+    pub fn actor_id(&self) -> ActorId {
+        self.actor_id
+    }
+
+    /// Create a `Sender<T>` delivering messages to this probe.
+    pub fn sender(&self) -> Sender<T>
+    where
+        T: Message<Result = ()>,
+    {
+        let tx = self.tx.clone();
+        Sender {
+            actor_id: self.actor_id,
+            sender_fn: Box::new(move |msg| {
+                tx.unbounded_send(msg)
+                    .map_err(|_| ActorError::Disconnected)?;
+                Ok(())
+            }),
+        }
+    }
+
+    /// Create a `Caller<T>` delivering messages to this probe.
+    ///
+    /// Each call is answered by the function set with `reply_with`, or otherwise waits for `reply`.
+    pub fn caller(&self) -> Caller<T> {
+        let tx = self.tx.clone();
+        let replies = self.replies.clone();
+
+        let closure = move |msg: T| {
+            let res = {
+                let mut replies = replies.lock().unwrap();
+                match &replies.reply_with {
+                    Some(reply_with) => Ok(reply_with(&msg)),
+                    None => {
+                        let (reply_tx, reply_rx) = oneshot::channel();
+                        replies.pending.push_back(reply_tx);
+                        Err(reply_rx)
+                    }
+                }
+            };
+            let sent = tx.unbounded_send(msg);
+            Box::pin(async move {
+                sent.map_err(|_| ActorError::Disconnected)?;
+                match res {
+                    Ok(res) => Ok(res),
+                    Err(reply_rx) => Ok(reply_rx.await.map_err(|_| ActorError::Disconnected)?),
+                }
+            }) as Pin<Box<dyn Future<Output = Result<T::Result>> + Send>>
+        };
+
+        Caller {
+            actor_id: self.actor_id,
+            caller_fn: Box::new(closure),
+        }
+    }
+
+    /// Answer every call from now on with the result of `f`, instead of waiting for `reply`.
+    pub fn reply_with<F>(&mut self, f: F)
+    where
+        F: Fn(&T) -> T::Result + Send + 'static,
+    {
+        self.replies.lock().unwrap().reply_with = Some(Box::new(f));
+    }
+
+    /// Answer the oldest call still waiting for a reply.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no call is waiting for a reply.
+    pub fn reply(&mut self, result: T::Result) {
+        let reply_tx = self
+            .replies
+            .lock()
+            .unwrap()
+            .pending
+            .pop_front()
+            .expect("no call is waiting for a reply");
+        reply_tx.send(result).ok();
+    }
+
+    /// Wait for the next message, for at most 3 seconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no message is received in time.
+    pub async fn expect_msg(&mut self) -> T {
+        self.expect_msg_within(DEFAULT_EXPECT_TIMEOUT).await
+    }
+
+    /// Wait for the next message, for at most `within`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no message is received in time.
+    pub async fn expect_msg_within(&mut self, within: Duration) -> T {
+        match timeout(within, self.rx.next()).await {
+            Ok(Some(msg)) => msg,
+            _ => panic!("no message received within {:?}", within),
+        }
+    }
+
+    /// Check that no message is received for `within`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a message is received in that time.
+    pub async fn expect_no_msg(&mut self, within: Duration) {
+        if let Ok(Some(_)) = timeout(within, self.rx.next()).await {
+            panic!(
+                "unexpected message of type {} received",
+                std::any::type_name::<T>()
+            );
+        }
+    }
+
+    /// Take all the messages received so far, oldest first.
+    pub fn received(&mut self) -> Vec<T> {
+        let mut messages = Vec::new();
+        while let Ok(msg) = self.rx.try_recv() {
+            messages.push(msg);
+        }
+        messages
+    }
+}
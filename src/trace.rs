@@ -0,0 +1,79 @@
+//! Spans and lifecycle events recorded with `tracing`, when the `tracing` feature is enabled.
+//!
+//! Without the feature, every function here does nothing.
+
+#![cfg_attr(
+    not(feature = "tracing"),
+    allow(unused_variables, clippy::extra_unused_type_parameters)
+)]
+
+use crate::{ActorId, ExitReason};
+use futures::Future;
+#[cfg(feature = "tracing")]
+use std::any::type_name;
+#[cfg(feature = "tracing")]
+use tracing::Instrument;
+
+/// The span of the code that sent a message, captured when the message is sent.
+pub(crate) struct SenderSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl SenderSpan {
+    pub(crate) fn current() -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::Span::current(),
+        }
+    }
+
+    /// Run `handler`, the handler of a message of type `T` for the actor `A`,
+    /// in a child span of the sender's span.
+    pub(crate) async fn in_handler<A, T, F>(self, actor_id: ActorId, handler: F) -> F::Output
+    where
+        F: Future,
+    {
+        #[cfg(feature = "tracing")]
+        return handler
+            .instrument(tracing::info_span!(
+                parent: &self.span,
+                "handle",
+                actor_type = type_name::<A>(),
+                actor_id,
+                message_type = type_name::<T>(),
+            ))
+            .await;
+        #[cfg(not(feature = "tracing"))]
+        return handler.await;
+    }
+}
+
+pub(crate) fn started<A>(actor_id: ActorId) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(actor_type = type_name::<A>(), actor_id, "actor started");
+}
+
+pub(crate) fn stopped<A>(actor_id: ActorId, reason: &ExitReason) {
+    #[cfg(feature = "tracing")]
+    if reason.is_failure() {
+        tracing::warn!(
+            actor_type = type_name::<A>(),
+            actor_id,
+            ?reason,
+            "actor stopped"
+        );
+    } else {
+        tracing::debug!(
+            actor_type = type_name::<A>(),
+            actor_id,
+            ?reason,
+            "actor stopped"
+        );
+    }
+}
+
+pub(crate) fn restarted<A>(actor_id: ActorId) {
+    #[cfg(feature = "tracing")]
+    tracing::info!(actor_type = type_name::<A>(), actor_id, "actor restarted");
+}
@@ -0,0 +1,26 @@
+use std::thread::{self, ThreadId};
+use xactor::*;
+
+#[message(result = "ThreadId")]
+struct GetThread;
+
+struct MyActor;
+
+impl Actor for MyActor {}
+
+impl Handler<GetThread> for MyActor {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: GetThread) -> ThreadId {
+        thread::current().id()
+    }
+}
+
+#[xactor::main(flavor = "multi_thread", worker_threads = 2)]
+async fn main() -> Result<()> {
+    let addr = MyActor.start().await?;
+    let thread_id = addr.call(GetThread).await?;
+    // The runtime options only apply to tokio
+    if cfg!(feature = "runtime-tokio") {
+        assert_ne!(thread_id, thread::current().id());
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use metrics_util::MetricKind;
+use xactor::*;
+
+#[message(result = "i32")]
+struct Ping;
+
+struct MyActor;
+
+impl Actor for MyActor {}
+
+impl Handler<Ping> for MyActor {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Ping) -> i32 {
+        1
+    }
+}
+
+#[test]
+fn test_messages_processed() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+
+    // The recorder is local to this thread, so the actor must run on it
+    let actor_id = metrics::with_local_recorder(&recorder, || {
+        testing::run_test(RuntimeOptions::new().current_thread(), true, async {
+            let mut addr = MyActor.start().await.unwrap();
+            addr.call(Ping).await.unwrap();
+            addr.call(Ping).await.unwrap();
+            addr.stop(None).unwrap();
+            let actor_id = addr.actor_id();
+            addr.wait_for_stop().await;
+            actor_id
+        })
+    });
+
+    let processed = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .find_map(|(key, _, _, value)| {
+            let (kind, key) = key.into_parts();
+            let labels: Vec<_> = key
+                .labels()
+                .map(|label| (label.key().to_string(), label.value().to_string()))
+                .collect();
+            let matches = kind == MetricKind::Counter
+                && key.name() == "xactor_messages_processed_total"
+                && labels.contains(&("actor_id".to_string(), actor_id.to_string()))
+                && labels.contains(&(
+                    "message_type".to_string(),
+                    std::any::type_name::<Ping>().to_string(),
+                ));
+            matches.then_some(value)
+        });
+    assert_eq!(processed, Some(DebugValue::Counter(2)));
+}
@@ -0,0 +1,70 @@
+use std::thread::{self, ThreadId};
+use xactor::*;
+
+#[message(result = "ThreadId")]
+struct GetThread;
+
+#[message]
+struct Panic;
+
+struct MyActor;
+
+impl Actor for MyActor {}
+
+impl Handler<GetThread> for MyActor {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: GetThread) -> ThreadId {
+        thread::current().id()
+    }
+}
+
+impl Handler<Panic> for MyActor {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Panic) {
+        panic!("boom");
+    }
+}
+
+#[xactor::testing::test(assert_stopped)]
+async fn test_assert_stopped() -> Result<()> {
+    let mut addr = MyActor.start().await?;
+    addr.call(GetThread).await?;
+    addr.stop(None)?;
+    addr.wait_for_stop().await;
+    Ok(())
+}
+
+#[xactor::testing::test(assert_stopped)]
+#[should_panic(expected = "actors still running")]
+async fn test_assert_stopped_running() {
+    let addr = MyActor.start().await.unwrap();
+    // Leaked, so still running when the test returns
+    std::mem::forget(addr);
+}
+
+#[xactor::testing::test(assert_stopped)]
+#[should_panic(expected = "a handler panicked")]
+async fn test_assert_stopped_panicked() {
+    let addr = MyActor.start().await.unwrap();
+    addr.send(Panic).unwrap();
+    addr.wait_for_stop().await;
+}
+
+#[xactor::testing::test(flavor = "current_thread")]
+async fn test_current_thread() -> Result<()> {
+    let addr = MyActor.start().await?;
+    let thread_id = addr.call(GetThread).await?;
+    // The runtime options only apply to tokio
+    if cfg!(feature = "runtime-tokio") {
+        assert_eq!(thread_id, thread::current().id());
+    }
+    Ok(())
+}
+
+#[xactor::testing::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_multi_thread() -> Result<()> {
+    let addr = MyActor.start().await?;
+    let thread_id = addr.call(GetThread).await?;
+    if cfg!(feature = "runtime-tokio") {
+        assert_ne!(thread_id, thread::current().id());
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::sync::{Arc, Mutex};
+use tracing::span::{Attributes, Id};
+use tracing::{Instrument, Subscriber};
+use tracing_subscriber::layer::{Context as LayerContext, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{Layer, Registry};
+use xactor::*;
+
+/// The name of a span, with the name of its parent.
+type SpanParent = (&'static str, Option<&'static str>);
+
+/// Records every new span, with its parent.
+#[derive(Clone, Default)]
+struct SpanParents(Arc<Mutex<Vec<SpanParent>>>);
+
+impl<S> Layer<S> for SpanParents
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: LayerContext<'_, S>) {
+        let span = ctx.span(id).unwrap();
+        let parent = span.parent().map(|parent| parent.name());
+        self.0.lock().unwrap().push((span.name(), parent));
+    }
+}
+
+#[message(result = "i32")]
+struct Ping;
+
+struct MyActor;
+
+impl Actor for MyActor {}
+
+impl Handler<Ping> for MyActor {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: Ping) -> i32 {
+        1
+    }
+}
+
+#[test]
+fn test_handler_span_parent() {
+    let spans = SpanParents::default();
+    let subscriber = Registry::default().with(spans.clone());
+
+    // The subscriber is local to this thread, so the actor must run on it
+    tracing::subscriber::with_default(subscriber, || {
+        testing::run_test(RuntimeOptions::new().current_thread(), true, async {
+            let mut addr = MyActor.start().await.unwrap();
+            addr.call(Ping)
+                .instrument(tracing::info_span!("request"))
+                .await
+                .unwrap();
+            addr.stop(None).unwrap();
+            addr.wait_for_stop().await;
+        })
+    });
+
+    let spans = spans.0.lock().unwrap();
+    assert!(
+        spans.contains(&("handle", Some("request"))),
+        "no handle span in the request span: {:?}",
+        spans
+    );
+}
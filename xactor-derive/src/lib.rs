@@ -50,18 +50,70 @@ pub fn message(args: TokenStream, input: TokenStream) -> TokenStream {
 
 /// Implement an xactor service type.
 ///
+/// The services it depends on can be listed with `#[service(depends_on(...))]`, see `xactor::Dependency`.
+///
 /// # Examples
 ///
 /// ```ignore
 /// #[derive(Service)]
+/// #[service(depends_on(DbPool))]
 /// struct TestActor;
 /// ```
-#[proc_macro_derive(Service)]
+#[proc_macro_derive(Service, attributes(service))]
 pub fn service(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let ident = &input.ident;
+
+    let mut dependencies = Vec::new();
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("service"))
+    {
+        let args = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list.nested,
+            _ => {
+                return Error::new_spanned(attr, "Expect #[service(depends_on(...))]")
+                    .to_compile_error()
+                    .into()
+            }
+        };
+        for arg in args {
+            match arg {
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("depends_on") => {
+                    for dependency in list.nested {
+                        match dependency {
+                            NestedMeta::Meta(Meta::Path(path)) => dependencies.push(path),
+                            other => {
+                                return Error::new_spanned(other, "Expect a service type")
+                                    .to_compile_error()
+                                    .into()
+                            }
+                        }
+                    }
+                }
+                other => {
+                    return Error::new_spanned(other, "Unknown argument")
+                        .to_compile_error()
+                        .into()
+                }
+            }
+        }
+    }
+
+    let dependencies_fn = if dependencies.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            fn dependencies() -> Vec<xactor::Dependency> {
+                vec![#(xactor::Dependency::of::<#dependencies>()),*]
+            }
+        }
+    };
     let expanded = quote! {
-        impl xactor::Service for #ident {}
+        impl xactor::Service for #ident {
+            #dependencies_fn
+        }
     };
     expanded.into()
 }
@@ -86,9 +138,20 @@ pub fn actor(input: TokenStream) -> TokenStream {
 
 /// Implement an xactor main function.
 ///
-/// Wait for all actors to exit.
+/// Once the function returns, all running actors are stopped with `xactor::System::shutdown`.
+///
+/// The tokio runtime can be configured with the `flavor` (`"multi_thread"` or `"current_thread"`)
+/// and `worker_threads` options, which are ignored by the other runtimes.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[xactor::main(flavor = "multi_thread", worker_threads = 2)]
+/// async fn main() {}
+/// ```
 #[proc_macro_attribute]
-pub fn main(_args: TokenStream, input: TokenStream) -> TokenStream {
+pub fn main(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
     let mut input = syn::parse_macro_input!(input as syn::ItemFn);
 
     if &*input.sig.ident.to_string() != "main" {
@@ -103,6 +166,11 @@ pub fn main(_args: TokenStream, input: TokenStream) -> TokenStream {
         });
     }
 
+    let options = match runtime_options(args) {
+        Ok(options) => options,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
     input.sig.ident = Ident::new("__main", Span::call_site());
     let ret = &input.sig.output;
 
@@ -110,9 +178,130 @@ pub fn main(_args: TokenStream, input: TokenStream) -> TokenStream {
         #input
 
         fn main() #ret {
-            xactor::block_on(__main())
+            xactor::block_on_with(#options, async {
+                let res = __main().await;
+                xactor::System::shutdown(xactor::System::DEFAULT_SHUTDOWN_TIMEOUT)
+                    .await
+                    .ok();
+                res
+            })
         }
     };
 
     expanded.into()
 }
+
+/// Implement an xactor test function.
+///
+/// Re-exported as `xactor::testing::test`.
+///
+/// The test runs on a new runtime with `xactor::testing::run_test`, which starts it with an empty
+/// service registry and stops all running actors once it returns.
+/// Since that resets state shared by the whole process, every test of the binary that uses actors
+/// must use this attribute.
+///
+/// With the `assert_stopped` option, the test fails if any actor is still running when it returns,
+/// or if any actor stopped because a handler panicked.
+/// The `flavor` and `worker_threads` options are the same as for `#[xactor::main]`.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[xactor::testing::test(assert_stopped)]
+/// async fn test_my_actor() -> Result<()> {
+///     let addr = MyActor.start().await?;
+///     assert_eq!(addr.call(Ping).await?, "pong");
+///     Ok(())
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let mut input = syn::parse_macro_input!(input as syn::ItemFn);
+
+    if input.sig.asyncness.is_none() {
+        return TokenStream::from(quote_spanned! { input.span() =>
+            compile_error!("the async keyword is missing from the function declaration"),
+        });
+    }
+
+    if !input.sig.inputs.is_empty() {
+        return TokenStream::from(quote_spanned! { input.sig.inputs.span() =>
+            compile_error!("test functions cannot take arguments"),
+        });
+    }
+
+    let (assert_stopped, args): (Vec<_>, Vec<_>) = args.into_iter().partition(
+        |arg| matches!(arg, NestedMeta::Meta(Meta::Path(path)) if path.is_ident("assert_stopped")),
+    );
+    let assert_stopped = !assert_stopped.is_empty();
+
+    let options = match runtime_options(args) {
+        Ok(options) => options,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let attrs = std::mem::take(&mut input.attrs);
+    let vis = input.vis.clone();
+    let name = std::mem::replace(
+        &mut input.sig.ident,
+        Ident::new("__test", Span::call_site()),
+    );
+    input.vis = syn::Visibility::Inherited;
+    let ret = &input.sig.output;
+
+    let expanded = quote! {
+        #[::core::prelude::v1::test]
+        #(#attrs)*
+        #vis fn #name() #ret {
+            #input
+
+            xactor::testing::run_test(#options, #assert_stopped, __test())
+        }
+    };
+
+    expanded.into()
+}
+
+fn runtime_options(args: AttributeArgs) -> Result<proc_macro2::TokenStream, Error> {
+    let mut options = quote! { xactor::RuntimeOptions::new() };
+    let mut current_thread = false;
+    let mut worker_threads = None;
+
+    for arg in args {
+        match arg {
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("flavor") => match &nv.lit {
+                syn::Lit::Str(lit) if lit.value() == "current_thread" => {
+                    current_thread = true;
+                    options = quote! { #options.current_thread() };
+                }
+                syn::Lit::Str(lit) if lit.value() == "multi_thread" => {}
+                lit => {
+                    return Err(Error::new_spanned(
+                        lit,
+                        "Expect \"multi_thread\" or \"current_thread\"",
+                    ))
+                }
+            },
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("worker_threads") => {
+                match &nv.lit {
+                    syn::Lit::Int(lit) => {
+                        worker_threads = Some(lit.clone());
+                        options = quote! { #options.worker_threads(#lit) };
+                    }
+                    lit => return Err(Error::new_spanned(lit, "Expect integer")),
+                }
+            }
+            arg => return Err(Error::new_spanned(arg, "Unknown option")),
+        }
+    }
+
+    if let (true, Some(lit)) = (current_thread, worker_threads) {
+        return Err(Error::new_spanned(
+            lit,
+            "`worker_threads` is not supported by the current_thread flavor",
+        ));
+    }
+
+    Ok(options)
+}